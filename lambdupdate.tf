@@ -9,6 +9,115 @@ variable "aws_region" {}
 
 variable "code_bucket" {}
 
+variable "write_marker" {
+  default = false
+}
+
+variable "tag_source" {
+  default = false
+}
+
+variable "approval_topic_arn" {
+  default = ""
+}
+
+variable "approval_functions" {
+  default = ""
+}
+
+variable "approval_table" {
+  default = ""
+}
+
+variable "lock_table" {
+  default = ""
+}
+
+variable "rollout_alias" {
+  default = ""
+}
+
+variable "rollout_table" {
+  default = ""
+}
+
+variable "rollout_steps" {
+  default = "10,50,100"
+}
+
+variable "rollout_interval_secs" {
+  default = 600
+}
+
+variable "rollback_alarms" {
+  default = ""
+}
+
+variable "bake_secs" {
+  default = 0
+}
+
+variable "health_check_timeout_secs" {
+  default = 30
+}
+
+variable "health_check_rollback" {
+  default = false
+}
+
+variable "metrics" {
+  default = false
+}
+
+variable "notification_topic_arn" {
+  default = ""
+}
+
+variable "notification_digest" {
+  default = false
+}
+
+variable "config_file" {
+  default = ""
+}
+
+variable "retry_queue" {
+  default = ""
+}
+
+variable "shadow_suffix" {
+  default = ""
+}
+
+variable "shadow_payload" {
+  default = "{}"
+}
+
+locals {
+  environment = {
+    LAMBDUPDATE_WRITE_MARKER              = var.write_marker
+    LAMBDUPDATE_TAG_SOURCE                = var.tag_source
+    LAMBDUPDATE_APPROVAL_TOPIC_ARN        = var.approval_topic_arn
+    LAMBDUPDATE_APPROVAL_FUNCTIONS        = var.approval_functions
+    LAMBDUPDATE_APPROVAL_TABLE            = var.approval_table
+    LAMBDUPDATE_LOCK_TABLE                = var.lock_table
+    LAMBDUPDATE_ROLLOUT_ALIAS             = var.rollout_alias
+    LAMBDUPDATE_ROLLOUT_TABLE             = var.rollout_table
+    LAMBDUPDATE_ROLLOUT_STEPS             = var.rollout_steps
+    LAMBDUPDATE_ROLLOUT_INTERVAL_SECS     = var.rollout_interval_secs
+    LAMBDUPDATE_ROLLBACK_ALARMS           = var.rollback_alarms
+    LAMBDUPDATE_BAKE_SECS                 = var.bake_secs
+    LAMBDUPDATE_HEALTH_CHECK_TIMEOUT_SECS = var.health_check_timeout_secs
+    LAMBDUPDATE_HEALTH_CHECK_ROLLBACK     = var.health_check_rollback
+    LAMBDUPDATE_METRICS                   = var.metrics
+    LAMBDUPDATE_NOTIFICATION_TOPIC_ARN    = var.notification_topic_arn
+    LAMBDUPDATE_NOTIFICATION_DIGEST       = var.notification_digest
+    LAMBDUPDATE_CONFIG_FILE               = var.config_file
+    LAMBDUPDATE_SHADOW_SUFFIX             = var.shadow_suffix
+    LAMBDUPDATE_SHADOW_PAYLOAD            = var.shadow_payload
+  }
+}
+
 provider "aws" {
   region = var.aws_region
 }
@@ -56,7 +165,16 @@ resource "aws_iam_role_policy_attachment" "cw_logs" {
 
 data "aws_iam_policy_document" "lambda" {
   statement {
-    actions   = ["lambda:UpdateFunctionCode"]
+    actions = [
+      "lambda:UpdateFunctionCode",
+      "lambda:GetFunction",
+      "lambda:GetFunctionConfiguration",
+      "lambda:ListTags",
+      "lambda:PublishVersion",
+      "lambda:GetAlias",
+      "lambda:UpdateAlias",
+      "lambda:InvokeFunction",
+    ]
     resources = ["*"]
   }
 }
@@ -76,6 +194,22 @@ data "aws_iam_policy_document" "s3" {
     actions   = ["s3:GetObject"]
     resources = ["${data.aws_s3_bucket.code_bucket.arn}/*"]
   }
+
+  # Without it, S3 reports deleted objects as 403 Forbidden rather than 404 Not Found.
+  statement {
+    actions   = ["s3:ListBucket"]
+    resources = [data.aws_s3_bucket.code_bucket.arn]
+  }
+
+  statement {
+    actions   = ["s3:GetObjectTagging", "s3:PutObjectTagging"]
+    resources = ["${data.aws_s3_bucket.code_bucket.arn}/*"]
+  }
+
+  statement {
+    actions   = ["s3:PutObject"]
+    resources = ["${data.aws_s3_bucket.code_bucket.arn}/*.deployed.json"]
+  }
 }
 
 resource "aws_iam_policy" "s3" {
@@ -88,6 +222,283 @@ resource "aws_iam_role_policy_attachment" "s3" {
   policy_arn = aws_iam_policy.s3.arn
 }
 
+data "aws_iam_policy_document" "sns" {
+  count = var.approval_topic_arn == "" ? 0 : 1
+
+  statement {
+    actions   = ["sns:Publish"]
+    resources = [var.approval_topic_arn]
+  }
+}
+
+resource "aws_iam_policy" "sns" {
+  count  = var.approval_topic_arn == "" ? 0 : 1
+  name   = "lambdupdate.sns.${var.aws_region}"
+  policy = data.aws_iam_policy_document.sns[0].json
+}
+
+resource "aws_iam_role_policy_attachment" "sns" {
+  count      = var.approval_topic_arn == "" ? 0 : 1
+  role       = aws_iam_role.lambdupdate.name
+  policy_arn = aws_iam_policy.sns[0].arn
+}
+
+data "aws_iam_policy_document" "notifications" {
+  count = var.notification_topic_arn == "" ? 0 : 1
+
+  statement {
+    actions   = ["sns:Publish"]
+    resources = [var.notification_topic_arn]
+  }
+}
+
+resource "aws_iam_policy" "notifications" {
+  count  = var.notification_topic_arn == "" ? 0 : 1
+  name   = "lambdupdate.notifications.${var.aws_region}"
+  policy = data.aws_iam_policy_document.notifications[0].json
+}
+
+resource "aws_iam_role_policy_attachment" "notifications" {
+  count      = var.notification_topic_arn == "" ? 0 : 1
+  role       = aws_iam_role.lambdupdate.name
+  policy_arn = aws_iam_policy.notifications[0].arn
+}
+
+resource "aws_dynamodb_table" "approvals" {
+  count        = var.approval_table == "" ? 0 : 1
+  name         = var.approval_table
+  billing_mode = "PAY_PER_REQUEST"
+  hash_key     = "token"
+
+  attribute {
+    name = "token"
+    type = "S"
+  }
+
+  ttl {
+    attribute_name = "expires_at"
+    enabled        = true
+  }
+}
+
+data "aws_iam_policy_document" "approvals" {
+  count = var.approval_table == "" ? 0 : 1
+
+  statement {
+    actions   = ["dynamodb:GetItem", "dynamodb:PutItem", "dynamodb:DeleteItem"]
+    resources = [aws_dynamodb_table.approvals[0].arn]
+  }
+}
+
+resource "aws_iam_policy" "approvals" {
+  count  = var.approval_table == "" ? 0 : 1
+  name   = "lambdupdate.approvals.${var.aws_region}"
+  policy = data.aws_iam_policy_document.approvals[0].json
+}
+
+resource "aws_iam_role_policy_attachment" "approvals" {
+  count      = var.approval_table == "" ? 0 : 1
+  role       = aws_iam_role.lambdupdate.name
+  policy_arn = aws_iam_policy.approvals[0].arn
+}
+
+resource "aws_dynamodb_table" "locks" {
+  count        = var.lock_table == "" ? 0 : 1
+  name         = var.lock_table
+  billing_mode = "PAY_PER_REQUEST"
+  hash_key     = "function_name"
+
+  attribute {
+    name = "function_name"
+    type = "S"
+  }
+
+  ttl {
+    attribute_name = "expires_at"
+    enabled        = true
+  }
+}
+
+data "aws_iam_policy_document" "dynamodb" {
+  count = var.lock_table == "" ? 0 : 1
+
+  statement {
+    actions   = ["dynamodb:PutItem", "dynamodb:DeleteItem"]
+    resources = [aws_dynamodb_table.locks[0].arn]
+  }
+}
+
+resource "aws_iam_policy" "dynamodb" {
+  count  = var.lock_table == "" ? 0 : 1
+  name   = "lambdupdate.dynamodb.${var.aws_region}"
+  policy = data.aws_iam_policy_document.dynamodb[0].json
+}
+
+resource "aws_iam_role_policy_attachment" "dynamodb" {
+  count      = var.lock_table == "" ? 0 : 1
+  role       = aws_iam_role.lambdupdate.name
+  policy_arn = aws_iam_policy.dynamodb[0].arn
+}
+
+resource "aws_dynamodb_table" "rollouts" {
+  count        = var.rollout_table == "" ? 0 : 1
+  name         = var.rollout_table
+  billing_mode = "PAY_PER_REQUEST"
+  hash_key     = "function_name"
+
+  attribute {
+    name = "function_name"
+    type = "S"
+  }
+}
+
+data "aws_iam_policy_document" "rollouts" {
+  count = var.rollout_table == "" ? 0 : 1
+
+  statement {
+    actions   = ["dynamodb:GetItem", "dynamodb:PutItem", "dynamodb:Scan"]
+    resources = [aws_dynamodb_table.rollouts[0].arn]
+  }
+
+  statement {
+    actions   = ["cloudwatch:GetMetricStatistics", "cloudwatch:DescribeAlarms"]
+    resources = ["*"]
+  }
+}
+
+resource "aws_iam_policy" "rollouts" {
+  count  = var.rollout_table == "" ? 0 : 1
+  name   = "lambdupdate.rollouts.${var.aws_region}"
+  policy = data.aws_iam_policy_document.rollouts[0].json
+}
+
+resource "aws_iam_role_policy_attachment" "rollouts" {
+  count      = var.rollout_table == "" ? 0 : 1
+  role       = aws_iam_role.lambdupdate.name
+  policy_arn = aws_iam_policy.rollouts[0].arn
+}
+
+data "aws_iam_policy_document" "metrics" {
+  count = var.metrics ? 1 : 0
+
+  statement {
+    actions   = ["cloudwatch:PutMetricData"]
+    resources = ["*"]
+
+    condition {
+      test     = "StringEquals"
+      variable = "cloudwatch:namespace"
+      values   = ["LambdUpdate"]
+    }
+  }
+}
+
+resource "aws_iam_policy" "metrics" {
+  count  = var.metrics ? 1 : 0
+  name   = "lambdupdate.metrics.${var.aws_region}"
+  policy = data.aws_iam_policy_document.metrics[0].json
+}
+
+resource "aws_iam_role_policy_attachment" "metrics" {
+  count      = var.metrics ? 1 : 0
+  role       = aws_iam_role.lambdupdate.name
+  policy_arn = aws_iam_policy.metrics[0].arn
+}
+
+resource "aws_cloudwatch_event_rule" "rollouts" {
+  count               = var.rollout_table == "" ? 0 : 1
+  name                = "lambdupdate-rollouts"
+  schedule_expression = "rate(1 minute)"
+}
+
+resource "aws_cloudwatch_event_target" "rollouts" {
+  count = var.rollout_table == "" ? 0 : 1
+  rule  = aws_cloudwatch_event_rule.rollouts[0].name
+  arn   = aws_lambda_function.lambdupdate.arn
+  input = jsonencode({ rollout = {} })
+}
+
+resource "aws_lambda_permission" "allow_rollouts" {
+  count         = var.rollout_table == "" ? 0 : 1
+  statement_id  = "lambdupdate-AllowExecutionFromRolloutSchedule"
+  action        = "lambda:InvokeFunction"
+  function_name = aws_lambda_function.lambdupdate.arn
+  principal     = "events.amazonaws.com"
+  source_arn    = aws_cloudwatch_event_rule.rollouts[0].arn
+}
+
+resource "aws_sqs_queue" "retry_dead" {
+  count                     = var.retry_queue == "" ? 0 : 1
+  name                      = "${var.retry_queue}-dead"
+  message_retention_seconds = 1209600
+}
+
+resource "aws_sqs_queue" "retry" {
+  count                      = var.retry_queue == "" ? 0 : 1
+  name                       = var.retry_queue
+  message_retention_seconds  = 1209600
+  visibility_timeout_seconds = 5400
+
+  redrive_policy = jsonencode({
+    deadLetterTargetArn = aws_sqs_queue.retry_dead[0].arn
+    maxReceiveCount     = 5
+  })
+}
+
+data "aws_iam_policy_document" "retry" {
+  count = var.retry_queue == "" ? 0 : 1
+
+  statement {
+    actions   = ["sqs:SendMessage", "sqs:ReceiveMessage", "sqs:DeleteMessage", "sqs:GetQueueAttributes"]
+    resources = [aws_sqs_queue.retry[0].arn]
+  }
+}
+
+resource "aws_iam_policy" "retry" {
+  count  = var.retry_queue == "" ? 0 : 1
+  name   = "lambdupdate.retry.${var.aws_region}"
+  policy = data.aws_iam_policy_document.retry[0].json
+}
+
+resource "aws_iam_role_policy_attachment" "retry" {
+  count      = var.retry_queue == "" ? 0 : 1
+  role       = aws_iam_role.lambdupdate.name
+  policy_arn = aws_iam_policy.retry[0].arn
+}
+
+resource "aws_cloudwatch_log_group" "retry" {
+  count             = var.retry_queue == "" ? 0 : 1
+  name              = "/aws/lambda/lambdupdate-dlq"
+  retention_in_days = "7"
+}
+
+resource "aws_lambda_function" "retry" {
+  count         = var.retry_queue == "" ? 0 : 1
+  function_name = "lambdupdate-dlq"
+  s3_bucket     = var.code_bucket
+  s3_key        = "lambdupdate-dlq.zip"
+  role          = aws_iam_role.lambdupdate.arn
+  architectures = ["arm64"]
+  runtime       = "provided.al2"
+  handler       = "ignored"
+  publish       = "false"
+  description   = "Retry failed LambdUpdate deployments"
+  timeout       = 900
+  memory_size   = 512
+
+  environment {
+    variables = local.environment
+  }
+}
+
+resource "aws_lambda_event_source_mapping" "retry" {
+  count                   = var.retry_queue == "" ? 0 : 1
+  event_source_arn        = aws_sqs_queue.retry[0].arn
+  function_name           = aws_lambda_function.retry[0].arn
+  batch_size              = 1
+  function_response_types = ["ReportBatchItemFailures"]
+}
+
 resource "aws_s3_bucket_notification" "notification" {
   bucket = data.aws_s3_bucket.code_bucket.id
 
@@ -116,6 +527,18 @@ resource "aws_lambda_function" "lambdupdate" {
   handler       = "ignored"
   publish       = "false"
   description   = "Update Lambdas from code in ${var.code_bucket}"
-  timeout       = 5
-  memory_size   = 128
+  timeout       = 300
+  memory_size   = 512
+
+  environment {
+    variables = local.environment
+  }
+
+  dynamic "dead_letter_config" {
+    for_each = var.retry_queue == "" ? [] : [1]
+
+    content {
+      target_arn = aws_sqs_queue.retry[0].arn
+    }
+  }
 }
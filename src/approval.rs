@@ -0,0 +1,641 @@
+use crate::{Clients, Config, Record, Target};
+use anyhow::{anyhow, Error, Result};
+use aws_sdk_dynamodb::types::AttributeValue;
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub(crate) const TOPIC_ARN_ENV_VAR: &str = "LAMBDUPDATE_APPROVAL_TOPIC_ARN";
+
+pub(crate) const FUNCTIONS_ENV_VAR: &str = "LAMBDUPDATE_APPROVAL_FUNCTIONS";
+
+pub(crate) const TTL_SECS_ENV_VAR: &str = "LAMBDUPDATE_APPROVAL_TTL_SECS";
+
+pub(crate) const TABLE_ENV_VAR: &str = "LAMBDUPDATE_APPROVAL_TABLE";
+
+pub(crate) const DEFAULT_TTL: Duration = Duration::hours(24);
+
+const APPROVAL_TAG_KEY: &str = "lambdupdate:approval";
+
+const APPROVAL_TAG_REQUIRED: &str = "required";
+
+const MAX_SUBJECT_LEN: usize = 100;
+
+const TOKEN_ATTR: &str = "token";
+
+const REQUEST_ATTR: &str = "request";
+
+const EXPIRES_AT_ATTR: &str = "expires_at";
+
+const APPROVED_ATTR: &str = "approved";
+
+const PENDING_KEY_PREFIX: &str = "pending";
+
+/// What the approval table records about an update which requires approval.
+#[derive(Debug, PartialEq)]
+enum Marker {
+    /// Nothing, so approval is requested for it now.
+    New,
+    Pending,
+    /// Approved, and already updated, which also lets retries update it again.
+    Approved,
+}
+
+/// A pending update to a function which requires approval. It is stored in the approval table and
+/// published to the approval topic wrapped as `{"approval": <request>}`, which is also the payload
+/// LambdUpdate expects to receive to approve it. Only payloads matching a stored request are
+/// honored, so the request's fields can't be altered by whoever approves it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApprovalRequest {
+    /// A random identifier for the request, which keys it in the approval table.
+    pub token: String,
+    #[serde(default)]
+    pub region: Option<String>,
+    pub function_name: String,
+    pub bucket: String,
+    pub key: String,
+    /// The package within a bundle to update the function with, if any.
+    #[serde(default)]
+    pub package: Option<String>,
+    pub e_tag: Option<String>,
+    pub requested_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+struct ApprovalMessage<'a> {
+    approval: &'a ApprovalRequest,
+}
+
+impl ApprovalRequest {
+    fn new(
+        record: &Record,
+        target: &Target,
+        e_tag: Option<String>,
+        requested_at: DateTime<Utc>,
+        ttl: Duration,
+    ) -> Self {
+        Self {
+            token: Uuid::new_v4().to_string(),
+            region: record.region.clone(),
+            function_name: target.function_name.clone(),
+            bucket: record.s3.bucket.name.clone(),
+            key: record.s3.object.key.clone(),
+            package: target.code.package_path().map(String::from),
+            e_tag,
+            requested_at,
+            expires_at: requested_at + ttl,
+        }
+    }
+
+    pub fn check_expiry(&self, now: DateTime<Utc>) -> Result<()> {
+        if now > self.expires_at {
+            Err(anyhow!(
+                "Approval for {} <-- {}:{} expired at {}",
+                self.function_name,
+                self.bucket,
+                self.key,
+                self.expires_at
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Identifies the update requested rather than the request, keying a record in the approval
+    /// table that it's pending so that retried deployments don't request approval for it again.
+    fn pending_key(&self) -> String {
+        format!(
+            "{}:{:?}",
+            PENDING_KEY_PREFIX,
+            (
+                &self.region,
+                &self.function_name,
+                &self.bucket,
+                &self.key,
+                &self.package,
+                &self.e_tag
+            )
+        )
+    }
+
+    /// Ensures an approval payload is exactly the request that was issued.
+    fn check_issued(&self, issued: &ApprovalRequest) -> Result<()> {
+        if self == issued {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Approval for {} <-- {}:{} doesn't match the request issued as {}",
+                self.function_name,
+                self.bucket,
+                self.key,
+                self.token
+            ))
+        }
+    }
+
+    fn check_e_tag(&self, e_tag: Option<&str>) -> Result<()> {
+        match self.e_tag.as_deref() {
+            Some(expected) if Some(expected) != e_tag => Err(anyhow!(
+                "{}:{} has changed since approval was requested: expected {}, found {:?}",
+                self.bucket,
+                self.key,
+                expected,
+                e_tag
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn subject(&self) -> String {
+        format!("LambdUpdate approval requested: {}", self.function_name)
+            .chars()
+            .take(MAX_SUBJECT_LEN)
+            .collect()
+    }
+
+    fn message(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&ApprovalMessage {
+            approval: self,
+        })?)
+    }
+}
+
+fn has_approval_tag(tags: &HashMap<String, String>) -> bool {
+    tags.get(APPROVAL_TAG_KEY)
+        .is_some_and(|v| v.eq_ignore_ascii_case(APPROVAL_TAG_REQUIRED))
+}
+
+/// Strips any version or alias from a function ARN, since tags belong to the function itself.
+fn unqualified_arn(function_arn: &str) -> &str {
+    match function_arn.match_indices(':').nth(6) {
+        Some((index, _)) => &function_arn[..index],
+        None => function_arn,
+    }
+}
+
+pub(crate) async fn is_required(
+    clients: &Clients,
+    config: &Config,
+    function_name: &str,
+) -> Result<bool> {
+    if config.approval_topic_arn.is_none() {
+        return Ok(false);
+    }
+
+    if config.approval_functions.iter().any(|f| f == function_name) {
+        debug!("Approval required by configuration: {}", function_name);
+        return Ok(true);
+    }
+
+    debug!("Get Function Configuration: {}", function_name);
+    let function_arn = clients
+        .lambda
+        .get_function_configuration()
+        .function_name(function_name)
+        .send()
+        .await?
+        .function_arn
+        .ok_or_else(|| anyhow!("No ARN returned for {}", function_name))?;
+    let function_arn = unqualified_arn(&function_arn);
+
+    // Missing tags fail rather than leave the function unprotected.
+    debug!("List Tags: {}", function_arn);
+    let output = clients
+        .lambda
+        .list_tags()
+        .resource(function_arn)
+        .send()
+        .await?;
+    let tags = output
+        .tags()
+        .ok_or_else(|| anyhow!("No tags returned for {}", function_name))?;
+
+    let required = has_approval_tag(tags);
+    if required {
+        debug!("Approval required by function tag: {}", function_name);
+    }
+
+    Ok(required)
+}
+
+/// Requests approval for a target's update, unless it's already been requested. Returns whether
+/// approval is pending, or `false` if this same update has already been approved and can go ahead.
+pub(crate) async fn request(
+    clients: &Clients,
+    config: &Config,
+    record: &Record,
+    target: &Target,
+) -> Result<bool> {
+    let topic_arn = config
+        .approval_topic_arn
+        .as_deref()
+        .ok_or_else(|| anyhow!("No approval topic configured"))?;
+
+    let bucket = &record.s3.bucket.name;
+    let key = &record.s3.object.key;
+
+    debug!("Head Object: {}:{}", bucket, key);
+    let e_tag = clients
+        .s3
+        .head_object()
+        .bucket(bucket)
+        .key(key)
+        .send()
+        .await?
+        .e_tag;
+
+    let request = ApprovalRequest::new(record, target, e_tag, Utc::now(), config.approval_ttl);
+
+    match mark_pending(clients, config, &request).await? {
+        Marker::New => {}
+        Marker::Pending => {
+            info!(
+                "Approval Already Requested: {} <-- {}",
+                target.function_name,
+                target.code.source(bucket, key)
+            );
+            return Ok(true);
+        }
+        Marker::Approved => {
+            info!(
+                "Update Already Approved: {} <-- {}",
+                target.function_name,
+                target.code.source(bucket, key)
+            );
+            return Ok(false);
+        }
+    }
+
+    let published = async {
+        store(clients, config, &request).await?;
+
+        debug!("Publish Approval Request: {} {:?}", topic_arn, request);
+        clients
+            .sns
+            .publish()
+            .topic_arn(topic_arn)
+            .subject(request.subject())
+            .message(request.message()?)
+            .send()
+            .await?;
+
+        Ok::<_, Error>(())
+    }
+    .await;
+
+    // Unmark the update, so that a retry requests approval for it again.
+    if let Err(e) = published {
+        delete(clients, config, &request.pending_key()).await;
+        return Err(e);
+    }
+
+    info!(
+        "Approval Requested: {} <-- {}, expires at {}",
+        target.function_name,
+        target.code.source(bucket, key),
+        request.expires_at
+    );
+
+    Ok(true)
+}
+
+fn table(config: &Config) -> Result<&str> {
+    config
+        .approval_table
+        .as_deref()
+        .ok_or_else(|| anyhow!("No approval table configured"))
+}
+
+/// Records that approval has been requested for an update, unless there's already an unexpired
+/// record of it, in which case that's returned instead.
+async fn mark_pending(
+    clients: &Clients,
+    config: &Config,
+    request: &ApprovalRequest,
+) -> Result<Marker> {
+    let table = table(config)?;
+    let pending_key = request.pending_key();
+    let now = Utc::now().timestamp();
+
+    debug!("Put Pending Approval: {} {}", table, pending_key);
+    let result = clients
+        .dynamodb
+        .put_item()
+        .table_name(table)
+        .item(TOKEN_ATTR, AttributeValue::S(pending_key.clone()))
+        .item(
+            EXPIRES_AT_ATTR,
+            AttributeValue::N(request.expires_at.timestamp().to_string()),
+        )
+        .condition_expression("attribute_not_exists(#t) OR #e < :now")
+        .expression_attribute_names("#t", TOKEN_ATTR)
+        .expression_attribute_names("#e", EXPIRES_AT_ATTR)
+        .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => {
+            info!("Put Pending Approval Succeeded: {}", pending_key);
+            return Ok(Marker::New);
+        }
+        Err(e)
+            if e.as_service_error()
+                .is_some_and(|e| e.is_conditional_check_failed_exception()) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    debug!("Get Pending Approval: {} {}", table, pending_key);
+    let output = clients
+        .dynamodb
+        .get_item()
+        .table_name(table)
+        .key(TOKEN_ATTR, AttributeValue::S(pending_key.clone()))
+        .consistent_read(true)
+        .send()
+        .await?;
+
+    let approved = output
+        .item()
+        .and_then(|item| item.get(APPROVED_ATTR))
+        .and_then(|v| v.as_bool().ok())
+        .is_some_and(|approved| *approved);
+
+    Ok(if approved {
+        Marker::Approved
+    } else {
+        Marker::Pending
+    })
+}
+
+/// Records that an update has been approved, until its request would have expired.
+async fn mark_approved(
+    clients: &Clients,
+    config: &Config,
+    request: &ApprovalRequest,
+) -> Result<()> {
+    let table = table(config)?;
+    let pending_key = request.pending_key();
+
+    debug!("Put Approved Update: {} {}", table, pending_key);
+    clients
+        .dynamodb
+        .put_item()
+        .table_name(table)
+        .item(TOKEN_ATTR, AttributeValue::S(pending_key.clone()))
+        .item(APPROVED_ATTR, AttributeValue::Bool(true))
+        .item(
+            EXPIRES_AT_ATTR,
+            AttributeValue::N(request.expires_at.timestamp().to_string()),
+        )
+        .send()
+        .await?;
+    info!("Put Approved Update Succeeded: {}", pending_key);
+
+    Ok(())
+}
+
+async fn store(clients: &Clients, config: &Config, request: &ApprovalRequest) -> Result<()> {
+    let table = table(config)?;
+
+    debug!("Put Approval Request: {} {}", table, request.token);
+    clients
+        .dynamodb
+        .put_item()
+        .table_name(table)
+        .item(TOKEN_ATTR, AttributeValue::S(request.token.clone()))
+        .item(
+            REQUEST_ATTR,
+            AttributeValue::S(serde_json::to_string(request)?),
+        )
+        .item(
+            EXPIRES_AT_ATTR,
+            AttributeValue::N(request.expires_at.timestamp().to_string()),
+        )
+        .condition_expression("attribute_not_exists(#t)")
+        .expression_attribute_names("#t", TOKEN_ATTR)
+        .send()
+        .await?;
+    info!("Put Approval Request Succeeded: {}", request.token);
+
+    Ok(())
+}
+
+/// Returns the stored request an approval payload refers to, failing if there isn't one (it was
+/// never issued, or has already been used) or the payload doesn't match it.
+pub(crate) async fn issued(
+    clients: &Clients,
+    config: &Config,
+    request: &ApprovalRequest,
+) -> Result<ApprovalRequest> {
+    let table = table(config)?;
+
+    debug!("Get Approval Request: {} {}", table, request.token);
+    let output = clients
+        .dynamodb
+        .get_item()
+        .table_name(table)
+        .key(TOKEN_ATTR, AttributeValue::S(request.token.clone()))
+        .consistent_read(true)
+        .send()
+        .await?;
+
+    let issued = output
+        .item()
+        .and_then(|item| item.get(REQUEST_ATTR))
+        .and_then(|v| v.as_s().ok())
+        .ok_or_else(|| anyhow!("No pending approval request: {}", request.token))?;
+    let issued: ApprovalRequest = serde_json::from_str(issued)?;
+    info!("Get Approval Request Succeeded: {}", request.token);
+
+    request.check_issued(&issued)?;
+
+    Ok(issued)
+}
+
+/// Removes a request once it's been approved, so it can't be used again, and records that its
+/// update was approved, so retries don't request approval for it again. Failures are logged rather
+/// than returned, since the update is already done and both records expire anyway.
+pub(crate) async fn consume(clients: &Clients, config: &Config, request: &ApprovalRequest) {
+    delete(clients, config, &request.token).await;
+
+    if let Err(e) = mark_approved(clients, config, request).await {
+        warn!(
+            "Put Approved Update Failed: {}: {:?}",
+            request.pending_key(),
+            e
+        );
+    }
+}
+
+async fn delete(clients: &Clients, config: &Config, key: &str) {
+    let Ok(table) = table(config) else {
+        return;
+    };
+
+    debug!("Delete Approval Request: {} {}", table, key);
+    let result = clients
+        .dynamodb
+        .delete_item()
+        .table_name(table)
+        .key(TOKEN_ATTR, AttributeValue::S(key.to_string()))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => info!("Delete Approval Request Succeeded: {}", key),
+        Err(e) => warn!("Delete Approval Request Failed: {}: {:?}", key, e),
+    }
+}
+
+/// Ensures the artifact hasn't been replaced since approval was requested.
+pub(crate) async fn check_artifact(clients: &Clients, request: &ApprovalRequest) -> Result<()> {
+    debug!("Head Object: {}:{}", request.bucket, request.key);
+    let output = clients
+        .s3
+        .head_object()
+        .bucket(&request.bucket)
+        .key(&request.key)
+        .send()
+        .await?;
+
+    request.check_e_tag(output.e_tag())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Code;
+
+    fn approval_request(e_tag: Option<&str>) -> ApprovalRequest {
+        let record = Record {
+            region: Some("us-east-1".to_string()),
+            s3: ("bucket", "foo.zip").into(),
+        };
+
+        let target = Target {
+            function_name: "foo".to_string(),
+            code: Code::Object,
+            order: 0,
+            requires_approval: true,
+        };
+
+        ApprovalRequest::new(
+            &record,
+            &target,
+            e_tag.map(String::from),
+            DateTime::UNIX_EPOCH,
+            DEFAULT_TTL,
+        )
+    }
+
+    #[test]
+    fn test_check_expiry() {
+        let request = approval_request(None);
+
+        assert!(request.check_expiry(DateTime::UNIX_EPOCH).is_ok());
+        assert!(request.check_expiry(request.expires_at).is_ok());
+
+        let res = request.check_expiry(request.expires_at + Duration::seconds(1));
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert!(e.to_string().contains("expired"));
+        }
+    }
+
+    #[test]
+    fn test_check_issued() {
+        let issued = approval_request(Some("abc"));
+        assert!(issued.clone().check_issued(&issued).is_ok());
+
+        let mut tampered = issued.clone();
+        tampered.expires_at += Duration::days(365);
+        let res = tampered.check_issued(&issued);
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert!(e.to_string().contains("doesn't match the request issued"));
+        }
+
+        let mut tampered = issued.clone();
+        tampered.e_tag = None;
+        assert!(tampered.check_issued(&issued).is_err());
+
+        let mut tampered = issued.clone();
+        tampered.function_name = "bar".to_string();
+        assert!(tampered.check_issued(&issued).is_err());
+    }
+
+    #[test]
+    fn test_pending_key() {
+        let request = approval_request(Some("abc"));
+
+        let mut retried = approval_request(Some("abc"));
+        retried.requested_at += Duration::minutes(5);
+        retried.expires_at += Duration::minutes(5);
+        assert_ne!(request.token, retried.token);
+        assert_eq!(request.pending_key(), retried.pending_key());
+
+        assert_ne!(
+            request.pending_key(),
+            approval_request(Some("def")).pending_key()
+        );
+
+        let mut packaged = request.clone();
+        packaged.package = Some("foo.zip".to_string());
+        assert_ne!(request.pending_key(), packaged.pending_key());
+    }
+
+    #[test]
+    fn test_check_e_tag() {
+        let request = approval_request(Some("abc"));
+
+        assert!(request.check_e_tag(Some("abc")).is_ok());
+        assert!(request.check_e_tag(Some("def")).is_err());
+        assert!(request.check_e_tag(None).is_err());
+
+        assert!(approval_request(None).check_e_tag(Some("def")).is_ok());
+    }
+
+    #[test]
+    fn test_has_approval_tag() {
+        let mut tags = HashMap::new();
+        assert!(!has_approval_tag(&tags));
+
+        tags.insert(APPROVAL_TAG_KEY.to_string(), "Required".to_string());
+        assert!(has_approval_tag(&tags));
+
+        tags.insert(APPROVAL_TAG_KEY.to_string(), "none".to_string());
+        assert!(!has_approval_tag(&tags));
+    }
+
+    #[test]
+    fn test_unqualified_arn() {
+        let arn = "arn:aws:lambda:us-east-1:123456789012:function:foo";
+        assert_eq!(arn, unqualified_arn(arn));
+        assert_eq!(arn, unqualified_arn(&format!("{}:prod", arn)));
+        assert_eq!(arn, unqualified_arn(&format!("{}:7", arn)));
+    }
+
+    #[test]
+    fn test_message_round_trip() -> Result<()> {
+        let request = approval_request(Some("abc"));
+
+        let message: serde_json::Value = serde_json::from_str(&request.message()?)?;
+        let parsed: ApprovalRequest = serde_json::from_value(message["approval"].clone())?;
+
+        assert_eq!(request, parsed);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_subject_truncated() {
+        let mut request = approval_request(None);
+        request.function_name = "f".repeat(MAX_SUBJECT_LEN);
+
+        assert_eq!(MAX_SUBJECT_LEN, request.subject().len());
+    }
+}
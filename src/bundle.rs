@@ -0,0 +1,469 @@
+use crate::{function_name, Clients, Code, Record, Target};
+use anyhow::{anyhow, Result};
+use base64::prelude::*;
+use log::{debug, info};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::io::{Cursor, Read, Seek};
+use std::sync::Arc;
+use zip::ZipArchive;
+
+/// Object metadata key naming the manifest within a bundle. Its presence marks the object as a bundle.
+pub(crate) const MANIFEST_MD_KEY: &str = "bundle.manifest";
+
+/// The largest package Lambda accepts as a direct upload.
+const MAX_PACKAGE_SIZE: u64 = 50 * 1024 * 1024;
+
+/// The largest bundle downloaded, since it's held in memory along with the packages extracted from
+/// it whose code has changed.
+const MAX_BUNDLE_SIZE: u64 = 100 * 1024 * 1024;
+
+/// Far larger than any real manifest, but small enough that a bad one can't exhaust memory.
+const MAX_MANIFEST_SIZE: u64 = 1024 * 1024;
+
+/// Maps package paths within a bundle to the functions to update with each package, e.g.
+/// `{"packages": {"orders.zip": ["orders-api"], "workers.zip": ["payments-worker", "email-sender"]}}`,
+/// optionally ordering packages' updates, e.g. `"order": {"workers.zip": 1}`. Packages with a lower
+/// order (`0` by default) are updated, successfully, before those with a higher one.
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    packages: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    order: BTreeMap<String, i64>,
+}
+
+impl Manifest {
+    fn parse(manifest: &[u8]) -> Result<Self> {
+        let manifest: Self = serde_json::from_slice(manifest)?;
+
+        if manifest.packages.is_empty() {
+            return Err(anyhow!("Bundle manifest lists no packages"));
+        }
+
+        if let Some(path) = manifest
+            .order
+            .keys()
+            .find(|path| !manifest.packages.contains_key(*path))
+        {
+            return Err(anyhow!(
+                "Bundle manifest orders {}, which isn't one of its packages",
+                path
+            ));
+        }
+
+        let mut function_names = HashSet::new();
+        for (path, package_function_names) in &manifest.packages {
+            if package_function_names.is_empty() {
+                return Err(anyhow!("Bundle manifest lists no functions for {}", path));
+            }
+
+            for function_name in package_function_names {
+                function_name::validate(function_name).map_err(|e| {
+                    anyhow!(
+                        "Invalid function name {:?} for {} in bundle manifest: {}",
+                        function_name,
+                        path,
+                        e
+                    )
+                })?;
+
+                if !function_names.insert(function_name) {
+                    return Err(anyhow!(
+                        "Bundle manifest lists {} for more than one package",
+                        function_name
+                    ));
+                }
+            }
+        }
+
+        Ok(manifest)
+    }
+}
+
+pub(crate) struct Package {
+    pub(crate) path: String,
+    pub(crate) function_names: Vec<String>,
+    pub(crate) order: i64,
+    pub(crate) zip: Vec<u8>,
+}
+
+fn check_bundle_size(bucket: &str, key: &str, size: Option<i64>) -> Result<()> {
+    match size {
+        Some(size) if size as u64 > MAX_BUNDLE_SIZE => Err(anyhow!(
+            "{}:{} is too large to read as a bundle: {} > {} bytes",
+            bucket,
+            key,
+            size,
+            MAX_BUNDLE_SIZE
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) async fn download(
+    s3_client: &aws_sdk_s3::Client,
+    bucket: &str,
+    key: &str,
+) -> Result<Vec<u8>> {
+    debug!("Get Object: {}:{}", bucket, key);
+    let output = s3_client
+        .get_object()
+        .bucket(bucket)
+        .key(key)
+        .send()
+        .await?;
+    check_bundle_size(bucket, key, output.content_length())?;
+    let bundle = output.body.collect().await?.to_vec();
+
+    info!(
+        "Get Object Succeeded: {}:{} ({} bytes)",
+        bucket,
+        key,
+        bundle.len()
+    );
+
+    Ok(bundle)
+}
+
+fn read_file<R>(archive: &mut ZipArchive<R>, path: &str, max_size: u64) -> Result<Vec<u8>>
+where
+    R: Read + Seek,
+{
+    let mut file = archive
+        .by_name(path)
+        .map_err(|e| anyhow!("Failed to find {} in bundle: {}", path, e))?;
+
+    if file.size() > max_size {
+        return Err(anyhow!(
+            "{} in bundle is too large: {} > {} bytes",
+            path,
+            file.size(),
+            max_size
+        ));
+    }
+
+    let mut contents = Vec::with_capacity(file.size() as usize);
+    file.read_to_end(&mut contents)?;
+
+    Ok(contents)
+}
+
+fn read_manifest<R>(archive: &mut ZipArchive<R>, manifest_path: &str) -> Result<Manifest>
+where
+    R: Read + Seek,
+{
+    let manifest = Manifest::parse(&read_file(archive, manifest_path, MAX_MANIFEST_SIZE)?)?;
+    debug!("Bundle Manifest: {:?}", manifest);
+
+    Ok(manifest)
+}
+
+/// Maps each of a bundle's package paths to the functions its manifest updates with it, without
+/// reading the packages themselves.
+pub(crate) fn package_functions(
+    bundle: &[u8],
+    manifest_path: &str,
+) -> Result<BTreeMap<String, Vec<String>>> {
+    let mut archive = ZipArchive::new(Cursor::new(bundle))?;
+    Ok(read_manifest(&mut archive, manifest_path)?.packages)
+}
+
+/// Reads a bundle's manifest, then each of its packages as it's iterated over, so that only the
+/// packages still needed are held in memory.
+pub(crate) fn read_packages<'a>(
+    bundle: &'a [u8],
+    manifest_path: &str,
+) -> Result<impl Iterator<Item = Result<Package>> + 'a> {
+    let mut archive = ZipArchive::new(Cursor::new(bundle))?;
+
+    let manifest = read_manifest(&mut archive, manifest_path)?;
+    let order = manifest.order;
+
+    Ok(manifest
+        .packages
+        .into_iter()
+        .map(move |(path, function_names)| {
+            let zip = read_file(&mut archive, &path, MAX_PACKAGE_SIZE)?;
+            Ok(Package {
+                order: order.get(&path).copied().unwrap_or_default(),
+                path,
+                function_names,
+                zip,
+            })
+        }))
+}
+
+/// Computes a package's hash the way Lambda reports it in `CodeSha256`.
+fn code_sha256(zip: &[u8]) -> String {
+    BASE64_STANDARD.encode(Sha256::digest(zip))
+}
+
+async fn get_code_sha256(
+    lambda_client: &aws_sdk_lambda::Client,
+    function_name: &str,
+) -> Option<String> {
+    debug!("Get Function Configuration: {}", function_name);
+    match lambda_client
+        .get_function_configuration()
+        .function_name(function_name)
+        .send()
+        .await
+    {
+        Ok(output) => output.code_sha256,
+        Err(e) => {
+            debug!(
+                "Get Function Configuration Failed: {}: {:?}",
+                function_name, e
+            );
+            None
+        }
+    }
+}
+
+/// Downloads a bundle and returns a target for each function listed in its manifest whose code
+/// differs from its package.
+pub(crate) async fn targets(
+    clients: &Clients,
+    record: &Record,
+    manifest_path: &str,
+) -> Result<Vec<Target>> {
+    let bundle = download(&clients.s3, &record.s3.bucket.name, &record.s3.object.key).await?;
+
+    let mut targets = Vec::new();
+
+    for package in read_packages(&bundle, manifest_path)? {
+        let package = package?;
+        debug!(
+            "Bundle Package: {} --> {:?}",
+            package.path, package.function_names
+        );
+
+        let package_sha256 = code_sha256(&package.zip);
+        let zip = Arc::<[u8]>::from(package.zip);
+
+        for function_name in package.function_names {
+            let function_sha256 = get_code_sha256(&clients.lambda, &function_name).await;
+            if function_sha256.as_deref() == Some(package_sha256.as_str()) {
+                info!(
+                    "Code Unchanged, Skipping: {} <-- {}",
+                    function_name, package.path
+                );
+                continue;
+            }
+
+            targets.push(Target {
+                function_name,
+                code: Code::Package {
+                    path: package.path.clone(),
+                    zip: zip.clone(),
+                },
+                order: package.order,
+                requires_approval: false,
+            });
+        }
+    }
+
+    Ok(targets)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    fn bundle(files: &[(&str, &[u8])]) -> Result<Vec<u8>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+        for (path, contents) in files {
+            writer.start_file(*path, options)?;
+            writer.write_all(contents)?;
+        }
+
+        Ok(writer.finish()?.into_inner())
+    }
+
+    #[test]
+    fn test_read_packages() -> Result<()> {
+        let bundle = bundle(&[
+            (
+                "manifest.json",
+                br#"{"packages":{"orders.zip":["orders-api"],"workers/payments.zip":["payments-worker","email-sender"]}}"#,
+            ),
+            ("orders.zip", b"orders"),
+            ("workers/payments.zip", b"payments"),
+        ])?;
+
+        let packages = read_packages(&bundle, "manifest.json")?.collect::<Result<Vec<_>>>()?;
+
+        assert_eq!(2, packages.len());
+        assert_eq!("orders.zip", packages[0].path);
+        assert_eq!(vec!["orders-api"], packages[0].function_names);
+        assert_eq!(b"orders", packages[0].zip.as_slice());
+        assert_eq!("workers/payments.zip", packages[1].path);
+        assert_eq!(
+            vec!["payments-worker", "email-sender"],
+            packages[1].function_names
+        );
+        assert_eq!(b"payments", packages[1].zip.as_slice());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_package_functions() -> Result<()> {
+        // Packages missing from the bundle aren't read, so don't fail.
+        let bundle = bundle(&[(
+            "manifest.json",
+            br#"{"packages":{"orders.zip":["orders-api"],"workers.zip":["payments-worker"]}}"#,
+        )])?;
+
+        assert_eq!(
+            BTreeMap::from([
+                ("orders.zip".to_string(), vec!["orders-api".to_string()]),
+                (
+                    "workers.zip".to_string(),
+                    vec!["payments-worker".to_string()]
+                ),
+            ]),
+            package_functions(&bundle, "manifest.json")?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_packages_manifest_too_large() -> Result<()> {
+        let manifest = vec![b' '; MAX_MANIFEST_SIZE as usize + 1];
+        let bundle = bundle(&[("manifest.json", &manifest)])?;
+
+        let res = read_packages(&bundle, "manifest.json")
+            .and_then(|packages| packages.collect::<Result<Vec<_>>>());
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert!(e
+                .to_string()
+                .contains("manifest.json in bundle is too large"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_packages_missing_manifest() -> Result<()> {
+        let bundle = bundle(&[("orders.zip", b"orders")])?;
+
+        let res = read_packages(&bundle, "manifest.json")
+            .and_then(|packages| packages.collect::<Result<Vec<_>>>());
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert!(e.to_string().contains("manifest.json"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_packages_missing_package() -> Result<()> {
+        let bundle = bundle(&[(
+            "manifest.json",
+            br#"{"packages":{"orders.zip":["orders-api"]}}"#,
+        )])?;
+
+        let res = read_packages(&bundle, "manifest.json")
+            .and_then(|packages| packages.collect::<Result<Vec<_>>>());
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert!(e.to_string().contains("orders.zip"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_bundle_size() {
+        assert!(check_bundle_size("bucket", "bundle.zip", None).is_ok());
+        assert!(check_bundle_size("bucket", "bundle.zip", Some(MAX_BUNDLE_SIZE as i64)).is_ok());
+
+        let res = check_bundle_size("bucket", "bundle.zip", Some(MAX_BUNDLE_SIZE as i64 + 1));
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert!(e.to_string().contains("too large to read as a bundle"));
+        }
+    }
+
+    #[test]
+    fn test_code_sha256() {
+        assert_eq!(
+            "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=",
+            code_sha256(b"")
+        );
+        assert_eq!(
+            "uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=",
+            code_sha256(b"hello world")
+        );
+    }
+
+    #[test]
+    fn test_read_packages_order() -> Result<()> {
+        let bundle = bundle(&[
+            (
+                "manifest.json",
+                br#"{"packages":{"api.zip":["orders-api"],"worker.zip":["orders-worker"]},"order":{"api.zip":1}}"#,
+            ),
+            ("api.zip", b"api"),
+            ("worker.zip", b"worker"),
+        ])?;
+
+        let packages = read_packages(&bundle, "manifest.json")?.collect::<Result<Vec<_>>>()?;
+
+        assert_eq!(
+            vec![("api.zip", 1), ("worker.zip", 0)],
+            packages
+                .iter()
+                .map(|p| (p.path.as_str(), p.order))
+                .collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest_order_unknown_package() {
+        let res = Manifest::parse(br#"{"packages":{"a.zip":["foo"]},"order":{"b.zip":1}}"#);
+
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert!(e.to_string().contains("orders b.zip"));
+        }
+    }
+
+    #[test]
+    fn test_manifest_empty() {
+        assert!(Manifest::parse(br#"{"packages":{}}"#).is_err());
+        assert!(Manifest::parse(br#"{"packages":{"orders.zip":[]}}"#).is_err());
+    }
+
+    #[test]
+    fn test_manifest_invalid_function() {
+        let res = Manifest::parse(br#"{"packages":{"a.zip":["foo bar"]}}"#);
+
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert!(e.to_string().contains("\"foo bar\" for a.zip"));
+        }
+    }
+
+    #[test]
+    fn test_manifest_duplicate_function() {
+        let res = Manifest::parse(br#"{"packages":{"a.zip":["foo"],"b.zip":["foo"]}}"#);
+
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert!(e.to_string().contains("more than one package"));
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use crate::function_name;
+use anyhow::{anyhow, Result};
+use log::debug;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+pub(crate) const PATH_ENV_VAR: &str = "LAMBDUPDATE_CONFIG_FILE";
+
+/// Marks an entry in `function.names` as a group name rather than a function name.
+pub(crate) const GROUP_PREFIX: &str = "group:";
+
+/// Settings managed centrally rather than in every upload, e.g.
+/// `{"groups": {"checkout": ["orders-api", "payments-worker", "email-sender"]}}`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ConfigFile {
+    #[serde(default)]
+    pub(crate) groups: BTreeMap<String, Vec<String>>,
+}
+
+impl ConfigFile {
+    fn parse(contents: &str) -> Result<Self> {
+        let config_file: Self = serde_json::from_str(contents)?;
+
+        for (group, function_names) in &config_file.groups {
+            if function_names.is_empty() {
+                return Err(anyhow!("Group {} lists no functions", group));
+            }
+
+            for function_name in function_names {
+                function_name::validate(function_name).map_err(|e| {
+                    anyhow!(
+                        "Invalid function name {:?} in group {}: {}",
+                        function_name,
+                        group,
+                        e
+                    )
+                })?;
+            }
+        }
+
+        Ok(config_file)
+    }
+
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        debug!("Read Config File: {}", path.display());
+        let contents = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+
+        Self::parse(&contents).map_err(|e| anyhow!("Invalid {}: {}", path.display(), e))
+    }
+}
+
+/// Replaces each `group:<name>` in a comma-separated list of function names with the group's
+/// functions, dropping any duplicates.
+pub(crate) fn expand_groups(
+    function_names: &str,
+    groups: &BTreeMap<String, Vec<String>>,
+) -> Result<Vec<String>> {
+    let mut expanded = Vec::new();
+
+    for function_name in function_names.split(',') {
+        let group_function_names = match function_name.strip_prefix(GROUP_PREFIX) {
+            Some(group) => groups
+                .get(group)
+                .ok_or_else(|| anyhow!("Unknown group: {}", group))?
+                .clone(),
+            None => vec![function_name.to_string()],
+        };
+
+        for function_name in group_function_names {
+            if !expanded.contains(&function_name) {
+                expanded.push(function_name);
+            }
+        }
+    }
+
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn groups() -> BTreeMap<String, Vec<String>> {
+        ConfigFile::parse(
+            r#"{"groups":{"checkout":["orders-api","payments-worker","email-sender"],"email":["email-sender"]}}"#,
+        )
+        .expect("config file is valid")
+        .groups
+    }
+
+    #[test]
+    fn test_expand_groups() -> Result<()> {
+        let groups = groups();
+
+        assert_eq!(vec!["foo", "bar"], expand_groups("foo,bar", &groups)?);
+        assert_eq!(
+            vec!["foo", "orders-api", "payments-worker", "email-sender"],
+            expand_groups("foo,group:checkout,group:email", &groups)?
+        );
+
+        let res = expand_groups("group:shipping", &groups);
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert!(e.to_string().contains("Unknown group: shipping"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(ConfigFile::parse(r#"{"group":{}}"#).is_err());
+        assert!(ConfigFile::parse(r#"{"groups":{"checkout":[]}}"#).is_err());
+
+        let res = ConfigFile::parse(r#"{"groups":{"checkout":["orders api"]}}"#);
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert!(e.to_string().contains("in group checkout"));
+        }
+    }
+
+    #[test]
+    fn test_parse_empty() -> Result<()> {
+        assert!(ConfigFile::parse("{}")?.groups.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use lambda_runtime::{service_fn, LambdaEvent};
+use lambdupdate::{
+    listen_for_shutdown, retry, run_until_shutdown, serve_until_shutdown, set_up_logger, Config,
+};
+use log::{debug, error};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::error::Error;
+
+type LambdaError = Box<dyn Error + Send + Sync + 'static>;
+
+/// A batch of messages from LambdUpdate's dead-letter queue, each holding a failed payload.
+#[derive(Debug, Deserialize)]
+struct SqsEvent {
+    #[serde(rename = "Records")]
+    records: Vec<SqsMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SqsMessage {
+    #[serde(rename = "messageId")]
+    message_id: String,
+    body: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), LambdaError> {
+    listen_for_shutdown()?;
+    let shutdown_budget = Config::from_env()?.shutdown_budget;
+
+    let func = service_fn(function);
+    serve_until_shutdown(lambda_runtime::run(func), shutdown_budget).await?;
+    Ok(())
+}
+
+async fn function(event: LambdaEvent<Value>) -> Result<Value, LambdaError> {
+    set_up_logger(module_path!(), false)?;
+    debug!("Processing event: {:?}", event);
+
+    let config = Config::from_env()?;
+    let event: SqsEvent = serde_json::from_value(event.payload)?;
+
+    let retries = async {
+        // Failed messages are reported individually, so that only they return to the queue.
+        let mut batch_item_failures = Vec::new();
+
+        for message in event.records {
+            let result = match serde_json::from_str(&message.body) {
+                Ok(request) => retry(request, &config).await,
+                Err(e) => Err(e.into()),
+            };
+
+            if let Err(e) = result {
+                error!("Retry Failed: {}: {:?}", message.message_id, e);
+                batch_item_failures.push(json!({"itemIdentifier": message.message_id}));
+            }
+        }
+
+        Ok(json!({"batchItemFailures": batch_item_failures}))
+    };
+
+    Ok(run_until_shutdown(retries, config.shutdown_budget).await?)
+}
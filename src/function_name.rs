@@ -0,0 +1,149 @@
+use anyhow::{anyhow, Result};
+
+/// The longest function name, ARN, or partial ARN Lambda accepts.
+const MAX_LEN: usize = 140;
+
+const MAX_NAME_LEN: usize = 64;
+
+const MAX_QUALIFIER_LEN: usize = 128;
+
+const LATEST: &str = "$LATEST";
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+fn validate_part(part: &str, kind: &str, max_len: usize) -> Result<()> {
+    if part.is_empty() {
+        return Err(anyhow!("{} is empty", kind));
+    }
+
+    if part.len() > max_len {
+        return Err(anyhow!(
+            "{} is too long: {} > {} characters",
+            kind,
+            part.len(),
+            max_len
+        ));
+    }
+
+    match part.chars().find(|c| !is_name_char(*c)) {
+        Some(c) => Err(anyhow!("{} contains invalid character {:?}", kind, c)),
+        None => Ok(()),
+    }
+}
+
+fn validate_account_id(account_id: &str) -> Result<()> {
+    if account_id.len() == 12 && account_id.chars().all(|c| c.is_ascii_digit()) {
+        Ok(())
+    } else {
+        Err(anyhow!("account ID {:?} is not 12 digits", account_id))
+    }
+}
+
+/// Validates a name and optional version or alias, i.e. `name` or `name:qualifier`.
+fn validate_qualified_name(parts: &[&str]) -> Result<()> {
+    match parts {
+        [name] => validate_part(name, "name", MAX_NAME_LEN),
+        [name, qualifier] => {
+            validate_part(name, "name", MAX_NAME_LEN)?;
+            if *qualifier == LATEST {
+                Ok(())
+            } else {
+                validate_part(qualifier, "qualifier", MAX_QUALIFIER_LEN)
+            }
+        }
+        _ => Err(anyhow!("too many ':'-separated parts")),
+    }
+}
+
+/// Checks a function name, ARN (`arn:aws:lambda:<region>:<account>:function:<name>`), or partial ARN
+/// (`<account>:function:<name>`) against Lambda's naming rules, any of which may carry a version or
+/// alias qualifier.
+pub(crate) fn validate(function_name: &str) -> Result<()> {
+    if function_name.len() > MAX_LEN {
+        return Err(anyhow!(
+            "too long: {} > {} characters",
+            function_name.len(),
+            MAX_LEN
+        ));
+    }
+
+    let parts = function_name.split(':').collect::<Vec<_>>();
+
+    match parts.as_slice() {
+        ["arn", partition, service, region, account_id, "function", rest @ ..] => {
+            if !partition.starts_with("aws") {
+                return Err(anyhow!(
+                    "ARN partition {:?} is not an AWS partition",
+                    partition
+                ));
+            }
+            if *service != "lambda" {
+                return Err(anyhow!("ARN service {:?} is not lambda", service));
+            }
+            validate_part(region, "ARN region", MAX_NAME_LEN)?;
+            validate_account_id(account_id)?;
+            validate_qualified_name(rest)
+        }
+        ["arn", ..] => Err(anyhow!("not a Lambda function ARN")),
+        [account_id, "function", rest @ ..] => {
+            validate_account_id(account_id)?;
+            validate_qualified_name(rest)
+        }
+        _ => validate_qualified_name(&parts),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        for function_name in [
+            "foo",
+            "foo-bar_123",
+            "foo:prod",
+            "foo:$LATEST",
+            "foo:7",
+            "123456789012:function:foo",
+            "123456789012:function:foo:prod",
+            "arn:aws:lambda:us-east-1:123456789012:function:foo",
+            "arn:aws-us-gov:lambda:us-gov-west-1:123456789012:function:foo:7",
+        ] {
+            assert!(validate(function_name).is_ok(), "{}", function_name);
+        }
+    }
+
+    #[test]
+    fn test_validate_invalid() {
+        for (function_name, error) in [
+            ("", "name is empty"),
+            ("foo bar", "invalid character ' '"),
+            ("foo.zip", "invalid character '.'"),
+            ("foo:", "qualifier is empty"),
+            ("foo:prod:1", "too many"),
+            ("1234:function:foo", "not 12 digits"),
+            ("arn:aws:s3:::bucket", "not a Lambda function ARN"),
+            (
+                "arn:aws:sqs:us-east-1:123456789012:function:foo",
+                "not lambda",
+            ),
+            (
+                "arn:gcp:lambda:us-east-1:123456789012:function:foo",
+                "not an AWS partition",
+            ),
+        ] {
+            let res = validate(function_name);
+            assert!(res.is_err(), "{}", function_name);
+            if let Err(e) = res {
+                assert!(e.to_string().contains(error), "{}: {}", function_name, e);
+            }
+        }
+
+        assert!(validate(&"f".repeat(MAX_NAME_LEN)).is_ok());
+        assert!(validate(&"f".repeat(MAX_NAME_LEN + 1)).is_err());
+        assert!(validate(&"f".repeat(MAX_LEN + 1)).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::{anyhow, Result};
+use aws_sdk_lambda::client::Waiters;
+use chrono::{Duration, Utc};
+use log::{debug, info};
+
+/// Object metadata key naming a URL to poll after the artifact's functions are updated.
+pub(crate) const URL_MD_KEY: &str = "health.url";
+
+pub(crate) const TIMEOUT_SECS_ENV_VAR: &str = "LAMBDUPDATE_HEALTH_CHECK_TIMEOUT_SECS";
+
+pub(crate) const ROLLBACK_ENV_VAR: &str = "LAMBDUPDATE_HEALTH_CHECK_ROLLBACK";
+
+pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::seconds(30);
+
+const POLL_INTERVAL: Duration = Duration::seconds(2);
+
+const REQUEST_TIMEOUT: Duration = Duration::seconds(5);
+
+/// Waits for functions' code updates to finish, so that health checks exercise the new code.
+pub(crate) async fn wait_for_updates(
+    lambda_client: &aws_sdk_lambda::Client,
+    function_names: &[&str],
+    timeout: Duration,
+) -> Result<()> {
+    for function_name in function_names {
+        debug!("Wait For Function Update: {}", function_name);
+        lambda_client
+            .wait_until_function_updated()
+            .function_name(*function_name)
+            .wait(timeout.to_std()?)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Polls a URL until it responds successfully, failing if it doesn't within the timeout.
+pub(crate) async fn check(url: &str, timeout: Duration) -> Result<()> {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT.to_std()?)
+        .build()?;
+
+    let deadline = Utc::now() + timeout;
+
+    loop {
+        debug!("Health Check: {}", url);
+        match client.get(url).send().await {
+            Ok(response) if response.status().is_success() => {
+                info!("Health Check Succeeded: {} {}", url, response.status());
+                return Ok(());
+            }
+            Ok(response) => debug!("Health Check Unhealthy: {} {}", url, response.status()),
+            Err(e) => debug!("Health Check Failed: {}: {:?}", url, e),
+        }
+
+        if Utc::now() + POLL_INTERVAL > deadline {
+            return Err(anyhow!(
+                "{} not healthy after {}s",
+                url,
+                timeout.num_seconds()
+            ));
+        }
+
+        tokio::time::sleep(POLL_INTERVAL.to_std()?).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serves every request with the given status, returning the server's URL.
+    async fn serve(status: &'static str) -> Result<String> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}/health", listener.local_addr()?);
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).await;
+                let _ = stream
+                    .write_all(
+                        format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status).as_bytes(),
+                    )
+                    .await;
+            }
+        });
+
+        Ok(url)
+    }
+
+    #[tokio::test]
+    async fn test_check_healthy() -> Result<()> {
+        let url = serve("200 OK").await?;
+
+        check(&url, Duration::seconds(1)).await
+    }
+
+    #[tokio::test]
+    async fn test_check_unhealthy() -> Result<()> {
+        let url = serve("503 Service Unavailable").await?;
+
+        let res = check(&url, Duration::seconds(1)).await;
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert!(e.to_string().contains("not healthy"));
+        }
+
+        Ok(())
+    }
+}
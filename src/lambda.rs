@@ -1,5 +1,8 @@
 use lambda_runtime::{service_fn, LambdaEvent};
-use lambdupdate::{set_up_logger, update};
+use lambdupdate::{
+    advance_rollouts, approve, listen_for_shutdown, run_until_shutdown, serve_until_shutdown,
+    set_up_logger, update, Config, Request,
+};
 use log::debug;
 use serde_json::{json, Value};
 use std::error::Error;
@@ -8,8 +11,11 @@ type LambdaError = Box<dyn Error + Send + Sync + 'static>;
 
 #[tokio::main]
 async fn main() -> Result<(), LambdaError> {
+    listen_for_shutdown()?;
+    let shutdown_budget = Config::from_env()?.shutdown_budget;
+
     let func = service_fn(function);
-    lambda_runtime::run(func).await?;
+    serve_until_shutdown(lambda_runtime::run(func), shutdown_budget).await?;
     Ok(())
 }
 
@@ -17,7 +23,15 @@ async fn function(event: LambdaEvent<Value>) -> Result<Value, LambdaError> {
     set_up_logger(module_path!(), false)?;
     debug!("Processing event: {:?}", event);
 
-    update(serde_json::from_value(event.payload)?).await?;
+    let config = Config::from_env()?;
+    let request = async {
+        match serde_json::from_value(event.payload)? {
+            Request::Approval { approval } => approve(approval, &config).await,
+            Request::Rollout { rollout } => advance_rollouts(rollout, &config).await,
+            Request::Update(event) => update(event, &config).await,
+        }
+    };
+    run_until_shutdown(request, config.shutdown_budget).await?;
 
     Ok(json!({}))
 }
@@ -1,30 +1,315 @@
-use anyhow::{anyhow, Result};
-use aws_config::ConfigLoader;
+use anyhow::{anyhow, Error, Result};
+use aws_config::{ConfigLoader, SdkConfig};
 use aws_sdk_lambda::config::Region;
+use aws_sdk_lambda::operation::update_function_code::UpdateFunctionCodeOutput;
+use aws_sdk_lambda::primitives::Blob;
 use aws_sdk_s3::operation::head_object::HeadObjectOutput;
-use futures::future::try_join_all;
-use log::{debug, info, LevelFilter};
-use serde::Deserialize;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{Tag, Tagging};
+use chrono::{SecondsFormat, Utc};
+use futures::future::join_all;
+use log::{debug, error, info, warn, LevelFilter};
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
 use std::fmt::Display;
+use std::mem;
+use std::path::Path;
+use std::sync::Arc;
+
+mod approval;
+mod bundle;
+mod config_file;
+mod function_name;
+mod health;
+mod lock;
+mod notify;
+mod observability;
+mod retry;
+mod rollout;
+mod schema;
+mod shadow;
+mod shutdown;
+mod tui;
+
+pub use approval::ApprovalRequest;
+pub use observability::setup_observability;
+pub use retry::retry;
+pub use rollout::RolloutRequest;
+pub use shutdown::{listen_for_shutdown, run_until_shutdown, serve_until_shutdown};
+pub use tui::tui;
 
 const FUNCTION_NAME_MD_KEY: &str = "function.names";
 
-#[derive(Debug, Deserialize)]
+/// Object metadata key ordering an object's deployment relative to others in the same event.
+const ORDER_MD_KEY: &str = "deploy.order";
+
+/// Separates tiers of functions in `function.names`, which are updated in order, e.g. `a,b;c`.
+const TIER_SEPARATOR: char = ';';
+
+const WRITE_MARKER_ENV_VAR: &str = "LAMBDUPDATE_WRITE_MARKER";
+
+const MARKER_SUFFIX: &str = ".deployed.json";
+
+const TAG_SOURCE_ENV_VAR: &str = "LAMBDUPDATE_TAG_SOURCE";
+
+const STATUS_TAG_KEY: &str = "lambdupdate:status";
+
+const DEPLOYED_AT_TAG_KEY: &str = "lambdupdate:deployed-at";
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Write a `<key>.deployed.json` marker object next to each successfully deployed artifact.
+    pub write_marker: bool,
+    /// Tag each source object with the result of its deployment.
+    pub tag_source: bool,
+    /// SNS topic to publish approval requests to. Approvals are disabled when unset.
+    pub approval_topic_arn: Option<String>,
+    /// Functions which require approval, in addition to those tagged `lambdupdate:approval=required`.
+    pub approval_functions: Vec<String>,
+    /// How long an approval request remains valid.
+    pub approval_ttl: chrono::Duration,
+    /// DynamoDB table to hold pending approval requests in. Required when approvals are enabled.
+    pub approval_table: Option<String>,
+    /// DynamoDB table to hold per-function deployment locks in. Locking is disabled when unset.
+    pub lock_table: Option<String>,
+    /// How long to wait for another invocation to release a function's lock.
+    pub lock_wait: chrono::Duration,
+    /// Alias to shift traffic to newly published versions in steps. Rollouts are disabled when unset.
+    pub rollout_alias: Option<String>,
+    /// DynamoDB table to hold rollout state in.
+    pub rollout_table: Option<String>,
+    /// Percentages of the alias's traffic to shift to a new version at each step, ending at 100.
+    pub rollout_steps: Vec<u8>,
+    /// How long to wait between rollout steps.
+    pub rollout_interval: chrono::Duration,
+    /// Errors tolerated from a new version during each step before rolling back.
+    pub rollout_max_errors: f64,
+    /// CloudWatch alarms which roll a rollout back if any fire before it completes.
+    pub rollback_alarms: Vec<String>,
+    /// How long to keep watching a fully rolled out version for failures.
+    pub bake_period: chrono::Duration,
+    /// How long to wait for an artifact's `health.url` to respond successfully after an update.
+    pub health_check_timeout: chrono::Duration,
+    /// Roll rollouts back when their artifact's health check fails.
+    pub health_check_rollback: bool,
+    /// An SNS topic to notify of each function's update result.
+    pub notification_topic_arn: Option<String>,
+    /// Notify of all of an invocation's results in a single summary, rather than one per function.
+    pub notification_digest: bool,
+    /// Publish deployment metrics to CloudWatch, for `setup_observability`'s dashboard and alarms.
+    pub metrics: bool,
+    /// Named groups of functions, which `function.names` metadata can reference as `group:<name>`.
+    pub groups: BTreeMap<String, Vec<String>>,
+    /// When set, each function with a copy named with this suffix (e.g. `-canary`) has that copy
+    /// updated and smoke tested first, and is only updated itself if the smoke test succeeds.
+    pub shadow_suffix: Option<String>,
+    /// The payload shadow functions are invoked with to smoke test them.
+    pub shadow_payload: String,
+    /// How long in-flight updates get to finish after SIGTERM, when run via [run_until_shutdown].
+    pub shutdown_budget: chrono::Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            write_marker: false,
+            tag_source: false,
+            approval_topic_arn: None,
+            approval_functions: Vec::new(),
+            approval_ttl: approval::DEFAULT_TTL,
+            approval_table: None,
+            lock_table: None,
+            lock_wait: lock::DEFAULT_WAIT,
+            rollout_alias: None,
+            rollout_table: None,
+            rollout_steps: rollout::DEFAULT_STEPS.to_vec(),
+            rollout_interval: rollout::DEFAULT_INTERVAL,
+            rollout_max_errors: 0.0,
+            rollback_alarms: Vec::new(),
+            bake_period: chrono::Duration::zero(),
+            health_check_timeout: health::DEFAULT_TIMEOUT,
+            health_check_rollback: false,
+            notification_topic_arn: None,
+            notification_digest: false,
+            metrics: false,
+            groups: BTreeMap::new(),
+            shadow_suffix: None,
+            shadow_payload: shadow::DEFAULT_PAYLOAD.to_string(),
+            shutdown_budget: shutdown::DEFAULT_BUDGET,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self> {
+        let rollout_alias = env_var(rollout::ALIAS_ENV_VAR);
+        let rollout_table = env_var(rollout::TABLE_ENV_VAR);
+        if rollout_alias.is_some() && rollout_table.is_none() {
+            return Err(anyhow!(
+                "{} requires {}",
+                rollout::ALIAS_ENV_VAR,
+                rollout::TABLE_ENV_VAR
+            ));
+        }
+
+        let approval_topic_arn = env_var(approval::TOPIC_ARN_ENV_VAR);
+        let approval_table = env_var(approval::TABLE_ENV_VAR);
+        if approval_topic_arn.is_some() && approval_table.is_none() {
+            return Err(anyhow!(
+                "{} requires {}",
+                approval::TOPIC_ARN_ENV_VAR,
+                approval::TABLE_ENV_VAR
+            ));
+        }
+
+        let mut config = Self {
+            write_marker: env_flag(WRITE_MARKER_ENV_VAR)?,
+            tag_source: env_flag(TAG_SOURCE_ENV_VAR)?,
+            approval_topic_arn,
+            approval_functions: env_var(approval::FUNCTIONS_ENV_VAR)
+                .map(|f| split_list(&f))
+                .unwrap_or_default(),
+            approval_ttl: env_secs(approval::TTL_SECS_ENV_VAR, approval::DEFAULT_TTL)?,
+            approval_table,
+            lock_table: env_var(lock::TABLE_ENV_VAR),
+            lock_wait: env_secs(lock::WAIT_SECS_ENV_VAR, lock::DEFAULT_WAIT)?,
+            rollout_alias,
+            rollout_table,
+            rollout_steps: match env_var(rollout::STEPS_ENV_VAR) {
+                Some(steps) => rollout::parse_steps(&steps)?,
+                None => rollout::DEFAULT_STEPS.to_vec(),
+            },
+            rollout_interval: env_secs(rollout::INTERVAL_SECS_ENV_VAR, rollout::DEFAULT_INTERVAL)?,
+            rollout_max_errors: match env_var(rollout::MAX_ERRORS_ENV_VAR) {
+                Some(max_errors) => max_errors.trim().parse().map_err(|_| {
+                    anyhow!(
+                        "Invalid number for {}: {}",
+                        rollout::MAX_ERRORS_ENV_VAR,
+                        max_errors
+                    )
+                })?,
+                None => 0.0,
+            },
+            rollback_alarms: env_var(rollout::ALARMS_ENV_VAR)
+                .map(|a| split_list(&a))
+                .unwrap_or_default(),
+            bake_period: env_secs(rollout::BAKE_SECS_ENV_VAR, chrono::Duration::zero())?,
+            health_check_timeout: env_secs(health::TIMEOUT_SECS_ENV_VAR, health::DEFAULT_TIMEOUT)?,
+            health_check_rollback: env_flag(health::ROLLBACK_ENV_VAR)?,
+            notification_topic_arn: env_var(notify::TOPIC_ARN_ENV_VAR),
+            notification_digest: env_flag(notify::DIGEST_ENV_VAR)?,
+            metrics: env_flag(observability::METRICS_ENV_VAR)?,
+            groups: BTreeMap::new(),
+            shadow_suffix: env_var(shadow::SUFFIX_ENV_VAR),
+            shadow_payload: env_var(shadow::PAYLOAD_ENV_VAR)
+                .unwrap_or_else(|| shadow::DEFAULT_PAYLOAD.to_string()),
+            shutdown_budget: env_secs(shutdown::BUDGET_SECS_ENV_VAR, shutdown::default_budget())?,
+        };
+
+        // Alarm-gated rollbacks, bakes, and health check rollbacks only happen as part of a rollout.
+        if config.rollout_alias.is_none() {
+            if config.health_check_rollback {
+                return Err(anyhow!(
+                    "{} requires {}",
+                    health::ROLLBACK_ENV_VAR,
+                    rollout::ALIAS_ENV_VAR
+                ));
+            }
+            if !config.rollback_alarms.is_empty() {
+                return Err(anyhow!(
+                    "{} requires {}",
+                    rollout::ALARMS_ENV_VAR,
+                    rollout::ALIAS_ENV_VAR
+                ));
+            }
+            if config.bake_period > chrono::Duration::zero() {
+                return Err(anyhow!(
+                    "{} requires {}",
+                    rollout::BAKE_SECS_ENV_VAR,
+                    rollout::ALIAS_ENV_VAR
+                ));
+            }
+        }
+
+        if let Some(path) = env_var(config_file::PATH_ENV_VAR) {
+            config.load_file(path)?;
+        }
+
+        Ok(config)
+    }
+
+    /// Loads settings managed in a config file, currently named groups of functions.
+    pub fn load_file<P>(&mut self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let config_file = config_file::ConfigFile::load(path.as_ref())?;
+        self.groups = config_file.groups;
+
+        Ok(())
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|v| !v.trim().is_empty())
+}
+
+fn env_secs(name: &str, default: chrono::Duration) -> Result<chrono::Duration> {
+    match env_var(name) {
+        Some(secs) => secs
+            .trim()
+            .parse()
+            .map(chrono::Duration::seconds)
+            .map_err(|_| anyhow!("Invalid seconds for {}: {}", name, secs)),
+        None => Ok(default),
+    }
+}
+
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn env_flag(name: &str) -> Result<bool> {
+    match env_var(name) {
+        Some(value) => value
+            .trim()
+            .to_lowercase()
+            .parse()
+            .map_err(|_| anyhow!("Invalid boolean for {}: {}", name, value)),
+        None => Ok(false),
+    }
+}
+
+/// A payload LambdUpdate can be invoked with.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Request {
+    Approval { approval: ApprovalRequest },
+    Rollout { rollout: RolloutRequest },
+    Update(Event),
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct Event {
     #[serde(alias = "Records")]
     pub records: Vec<Record>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Record {
-    #[serde(alias = "awsRegion")]
-    pub region: String,
+    /// The bucket's region, absent from some synthetic or replayed events.
+    #[serde(alias = "awsRegion", default)]
+    pub region: Option<String>,
     pub s3: S3,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct S3 {
     pub bucket: Bucket,
     pub object: Object,
@@ -40,7 +325,7 @@ impl From<(&str, &str)> for S3 {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Bucket {
     pub name: String,
 }
@@ -53,7 +338,7 @@ impl From<&str> for Bucket {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Object {
     pub key: String,
 }
@@ -66,6 +351,130 @@ impl From<&str> for Object {
     }
 }
 
+#[derive(Debug, Serialize)]
+pub struct FunctionUpdate {
+    pub function_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    pub version: Option<String>,
+    pub code_sha256: Option<String>,
+}
+
+impl FunctionUpdate {
+    fn new(target: Target, output: UpdateFunctionCodeOutput) -> Self {
+        Self {
+            function_name: target.function_name,
+            package: target.code.package_path().map(String::from),
+            version: output.version,
+            code_sha256: output.code_sha256,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DeploymentStatus {
+    Succeeded,
+    Failed,
+    PendingApproval,
+}
+
+impl DeploymentStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Succeeded => "succeeded",
+            Self::Failed => "failed",
+            Self::PendingApproval => "pending-approval",
+        }
+    }
+}
+
+/// The code to update a function with.
+#[derive(Clone)]
+pub(crate) enum Code {
+    /// The triggering object.
+    Object,
+    /// A package extracted from a bundle, shared by the functions it updates.
+    Package { path: String, zip: Arc<[u8]> },
+}
+
+impl Code {
+    fn package_path(&self) -> Option<&str> {
+        match self {
+            Self::Object => None,
+            Self::Package { path, .. } => Some(path),
+        }
+    }
+
+    fn source(&self, bucket: &str, key: &str) -> String {
+        match self {
+            Self::Object => format!("{}:{}", bucket, key),
+            Self::Package { path, .. } => format!("{}:{}[{}]", bucket, key, path),
+        }
+    }
+}
+
+/// A function to update, and the code to update it with.
+pub(crate) struct Target {
+    pub(crate) function_name: String,
+    pub(crate) code: Code,
+    /// Within a deployment, targets with a lower order are updated, successfully, before those with
+    /// a higher one.
+    pub(crate) order: i64,
+    /// Whether to request approval rather than update the function.
+    pub(crate) requires_approval: bool,
+}
+
+/// A record and the functions to update from it.
+struct Deployment {
+    record: Record,
+    targets: Vec<Target>,
+    health_check_url: Option<String>,
+    /// Deployments with a lower order are deployed, successfully, before those with a higher one.
+    order: i64,
+}
+
+pub(crate) struct Clients {
+    pub(crate) s3: aws_sdk_s3::Client,
+    pub(crate) lambda: aws_sdk_lambda::Client,
+    pub(crate) sns: aws_sdk_sns::Client,
+    pub(crate) dynamodb: aws_sdk_dynamodb::Client,
+    pub(crate) cloudwatch: aws_sdk_cloudwatch::Client,
+}
+
+/// Loads AWS configuration for `region`, or for the default provider chain's region (e.g.
+/// `AWS_REGION`) if it's `None`. Setting a region provider at all, even an empty one, would bypass
+/// the default chain.
+pub(crate) async fn load_aws_config(region: Option<String>) -> SdkConfig {
+    let mut loader = ConfigLoader::default();
+    if let Some(region) = region {
+        loader = loader.region(Region::new(region));
+    }
+
+    loader.load().await
+}
+
+impl Clients {
+    async fn new(region: Option<String>) -> Self {
+        let aws_config = load_aws_config(region).await;
+
+        Self {
+            s3: aws_sdk_s3::Client::new(&aws_config),
+            lambda: aws_sdk_lambda::Client::new(&aws_config),
+            sns: aws_sdk_sns::Client::new(&aws_config),
+            dynamodb: aws_sdk_dynamodb::Client::new(&aws_config),
+            cloudwatch: aws_sdk_cloudwatch::Client::new(&aws_config),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct DeploymentMarker<'a> {
+    bucket: &'a str,
+    key: &'a str,
+    deployed_at: &'a str,
+    functions: &'a [FunctionUpdate],
+}
+
 pub fn set_up_logger<T>(calling_module: T, verbose: bool) -> Result<()>
 where
     T: Into<Cow<'static, str>>,
@@ -95,66 +504,129 @@ where
     Ok(())
 }
 
-fn get_region(records: &[Record]) -> Result<String> {
-    let regions = records.iter().map(|r| &r.region).collect::<HashSet<_>>();
+/// Returns the records' region, or `None` if they have none, to fall back to the default provider
+/// chain (e.g. `AWS_REGION`).
+fn get_region(records: &[Record]) -> Result<Option<String>> {
+    let regions = records
+        .iter()
+        .map(|r| r.region.as_ref())
+        .collect::<HashSet<_>>();
 
-    if regions.len() == 1 {
-        Ok(regions
-            .into_iter()
-            .find(|_| true)
-            .cloned()
-            .expect("regions has one element"))
-    } else {
-        Err(anyhow!("Invalid region count: {:?}", regions))
+    if regions.len() != 1 {
+        return Err(anyhow!("Invalid region count: {:?}", regions));
+    }
+
+    let region = regions
+        .into_iter()
+        .find(|_| true)
+        .expect("regions has one element")
+        .cloned();
+
+    if region.is_none() {
+        info!("No region in event records, falling back to the default region");
     }
+
+    Ok(region)
 }
 
-async fn get_function_names_from_md(
-    s3_client: &aws_sdk_s3::Client,
-    record: &Record,
-) -> Option<String> {
+async fn get_object_md(s3_client: &aws_sdk_s3::Client, record: &Record) -> HashMap<String, String> {
     let bucket = &record.s3.bucket.name;
     let key = &record.s3.object.key;
 
     debug!("Head Object: {}:{}", bucket, key);
     let head_object_output = s3_client.head_object().bucket(bucket).key(key).send().await;
-    get_function_names_from_head_object_output(head_object_output, bucket, key)
+    get_object_md_from_head_object_output(head_object_output, bucket, key)
 }
 
-fn get_function_names_from_head_object_output<E>(
+fn get_object_md_from_head_object_output<E>(
     head_object_output: Result<HeadObjectOutput, E>,
     bucket: &str,
     key: &str,
-) -> Option<String> {
+) -> HashMap<String, String> {
     if let Ok(head_object_output) = head_object_output {
         info!("Head Object Succeeded: {}:{}", bucket, key);
 
         let object_md = head_object_output.metadata;
         debug!("Object Metadata: {:?}", object_md);
 
-        object_md.and_then(|m| m.get(FUNCTION_NAME_MD_KEY).cloned())
+        object_md.unwrap_or_default()
     } else {
         info!("Head Object Failed: {}:{}", bucket, key);
-        None
+        HashMap::new()
     }
 }
 
-fn get_function_names<S>(function_names_from_md: Option<S>, record: &Record) -> Result<String>
+fn get_function_names<S>(
+    function_names_from_md: Option<S>,
+    record: &Record,
+    groups: &BTreeMap<String, Vec<String>>,
+) -> Result<String>
 where
     S: Into<String> + Display,
 {
+    let bucket = &record.s3.bucket.name;
+    let key = &record.s3.object.key;
+
     let function_names = match function_names_from_md {
         Some(function_names) => {
             debug!("Function names from object metadata: {}", function_names);
-            function_names.into()
+            let function_names: String = function_names.into();
+
+            let mut seen = HashSet::new();
+            let mut tiers = Vec::new();
+
+            for tier in function_names.split(TIER_SEPARATOR) {
+                let tier = config_file::expand_groups(tier, groups).map_err(|e| {
+                    anyhow!(
+                        "Invalid '{}' metadata of {}:{}: {}",
+                        FUNCTION_NAME_MD_KEY,
+                        bucket,
+                        key,
+                        e
+                    )
+                })?;
+
+                for function_name in &tier {
+                    function_name::validate(function_name).map_err(|e| {
+                        anyhow!(
+                            "Invalid function name {:?} in '{}' metadata of {}:{}: {}",
+                            function_name,
+                            FUNCTION_NAME_MD_KEY,
+                            bucket,
+                            key,
+                            e
+                        )
+                    })?;
+                }
+
+                // A function listed in more than one tier is updated in the first.
+                let tier = tier
+                    .into_iter()
+                    .filter(|function_name| seen.insert(function_name.clone()))
+                    .collect::<Vec<_>>();
+                if !tier.is_empty() {
+                    tiers.push(tier.join(","));
+                }
+            }
+
+            tiers.join(&TIER_SEPARATOR.to_string())
         }
         None => {
-            let key = &record.s3.object.key;
             let function_name = key
                 .strip_suffix(".zip")
                 .ok_or_else(|| anyhow!("'.zip' not found in object key: {}", key))?;
 
             debug!("Function name from object key: {}", function_name);
+            function_name::validate(function_name).map_err(|e| {
+                anyhow!(
+                    "Invalid function name {:?} from object key {}:{}: {}",
+                    function_name,
+                    bucket,
+                    key,
+                    e
+                )
+            })?;
+
             function_name.to_string()
         }
     };
@@ -162,85 +634,837 @@ where
     Ok(function_names)
 }
 
-async fn update_code(
-    lambda_client: aws_sdk_lambda::Client,
-    function_name: String,
-    bucket: String,
-    key: String,
-) -> Result<()> {
-    debug!(
-        "Update Function Code: {} <-- {}:{}",
-        function_name, bucket, key
-    );
+fn get_order(order_from_md: Option<&String>, record: &Record) -> Result<i64> {
+    match order_from_md {
+        Some(order) => order.trim().parse().map_err(|_| {
+            anyhow!(
+                "Invalid '{}' metadata of {}:{}: {}",
+                ORDER_MD_KEY,
+                record.s3.bucket.name,
+                record.s3.object.key,
+                order
+            )
+        }),
+        None => Ok(0),
+    }
+}
+
+async fn function_exists(
+    lambda_client: &aws_sdk_lambda::Client,
+    function_name: &str,
+) -> Result<bool> {
+    debug!("Get Function: {}", function_name);
+    match lambda_client
+        .get_function()
+        .function_name(function_name)
+        .send()
+        .await
+    {
+        Ok(_) => Ok(true),
+        Err(e)
+            if e.as_service_error()
+                .is_some_and(|e| e.is_resource_not_found_exception()) =>
+        {
+            Ok(false)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
 
-    lambda_client
+async fn update_function_code(
+    lambda_client: &aws_sdk_lambda::Client,
+    function_name: &str,
+    code: &Code,
+    bucket: &str,
+    key: &str,
+) -> Result<UpdateFunctionCodeOutput> {
+    let source = code.source(bucket, key);
+
+    debug!("Update Function Code: {} <-- {}", function_name, source);
+
+    let request = lambda_client
         .update_function_code()
-        .function_name(&function_name)
-        .s3_bucket(&bucket)
-        .s3_key(&key)
+        .function_name(function_name);
+
+    let request = match code {
+        Code::Object => request.s3_bucket(bucket).s3_key(key),
+        Code::Package { zip, .. } => request.zip_file(Blob::new(zip.to_vec())),
+    };
+
+    let output = request.send().await?;
+
+    info!(
+        "Update Function Code Succeeded: {} <-- {}",
+        function_name, source
+    );
+
+    Ok(output)
+}
+
+/// Updates functions' code, holding each function's lock and starting its rollout if configured.
+#[derive(Clone)]
+struct Updater {
+    lambda: aws_sdk_lambda::Client,
+    locks: Option<lock::Locks>,
+    rollouts: Option<rollout::Rollouts>,
+    shadows: Option<shadow::Shadows>,
+}
+
+impl Updater {
+    fn new(clients: &Clients, config: &Config) -> Self {
+        Self {
+            lambda: clients.lambda.clone(),
+            locks: lock::Locks::new(clients, config),
+            rollouts: rollout::Rollouts::new(clients, config),
+            shadows: shadow::Shadows::new(config),
+        }
+    }
+
+    async fn update_code(
+        self,
+        target: Target,
+        bucket: String,
+        key: String,
+    ) -> Result<FunctionUpdate> {
+        let lock = match &self.locks {
+            Some(locks) => Some(locks.acquire(&target.function_name).await?),
+            None => None,
+        };
+
+        let function_update = self.update_locked(target, &bucket, &key).await;
+
+        if let Some(lock) = lock {
+            lock.release().await;
+        }
+
+        function_update
+    }
+
+    /// Updates a function, after its shadow if it has one, once its lock is held. Neither is
+    /// started once shutdown is requested.
+    async fn update_locked(
+        &self,
+        target: Target,
+        bucket: &str,
+        key: &str,
+    ) -> Result<FunctionUpdate> {
+        let check_shutdown = || {
+            if shutdown::requested() {
+                Err(anyhow!(
+                    "Shutdown requested, not updating {}",
+                    target.function_name
+                ))
+            } else {
+                Ok(())
+            }
+        };
+
+        if let Some(shadows) = &self.shadows {
+            check_shutdown()?;
+            shadows.deploy(&self.lambda, &target, bucket, key).await?;
+        }
+
+        check_shutdown()?;
+        self.send_update_code(target, bucket, key).await
+    }
+
+    /// Checks an artifact's health once its functions have finished updating, rolling their
+    /// rollouts back if it's unhealthy and configured to.
+    async fn check_health(
+        &self,
+        url: &str,
+        function_updates: &[FunctionUpdate],
+        config: &Config,
+    ) -> Result<()> {
+        let function_names = function_updates
+            .iter()
+            .map(|f| f.function_name.as_str())
+            .collect::<Vec<_>>();
+        health::wait_for_updates(&self.lambda, &function_names, config.health_check_timeout)
+            .await?;
+
+        let result = health::check(url, config.health_check_timeout).await;
+
+        if let (Err(_), true, Some(rollouts)) =
+            (&result, config.health_check_rollback, &self.rollouts)
+        {
+            for function_update in function_updates {
+                if let Some(version) = &function_update.version {
+                    rollouts
+                        .roll_back(&function_update.function_name, version)
+                        .await?;
+                }
+            }
+        }
+
+        result
+    }
+
+    async fn send_update_code(
+        &self,
+        target: Target,
+        bucket: &str,
+        key: &str,
+    ) -> Result<FunctionUpdate> {
+        let output = update_function_code(
+            &self.lambda,
+            &target.function_name,
+            &target.code,
+            bucket,
+            key,
+        )
+        .await?;
+
+        let mut function_update = FunctionUpdate::new(target, output);
+
+        if let Some(rollouts) = &self.rollouts {
+            let version = rollouts
+                .start(
+                    &function_update.function_name,
+                    function_update.code_sha256.as_deref(),
+                )
+                .await?;
+            function_update.version = Some(version);
+        }
+
+        Ok(function_update)
+    }
+}
+
+fn marker_key(key: &str) -> String {
+    format!("{}{}", key, MARKER_SUFFIX)
+}
+
+fn timestamp() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn marker_body(
+    record: &Record,
+    function_updates: &[FunctionUpdate],
+    deployed_at: &str,
+) -> Result<Vec<u8>> {
+    let marker = DeploymentMarker {
+        bucket: &record.s3.bucket.name,
+        key: &record.s3.object.key,
+        deployed_at,
+        functions: function_updates,
+    };
+
+    Ok(serde_json::to_vec_pretty(&marker)?)
+}
+
+async fn write_marker(
+    s3_client: &aws_sdk_s3::Client,
+    record: &Record,
+    function_updates: &[FunctionUpdate],
+    deployed_at: &str,
+) -> Result<()> {
+    let bucket = &record.s3.bucket.name;
+    let key = marker_key(&record.s3.object.key);
+
+    debug!("Put Deployment Marker: {}:{}", bucket, key);
+
+    s3_client
+        .put_object()
+        .bucket(bucket)
+        .key(&key)
+        .content_type("application/json")
+        .body(ByteStream::from(marker_body(
+            record,
+            function_updates,
+            deployed_at,
+        )?))
         .send()
         .await?;
 
-    info!(
-        "Update Function Code Succeeded: {} <-- {}:{}",
-        function_name, bucket, key
+    info!("Put Deployment Marker Succeeded: {}:{}", bucket, key);
+
+    Ok(())
+}
+
+fn deployment_tags(
+    existing_tags: Vec<Tag>,
+    status: DeploymentStatus,
+    deployed_at: &str,
+) -> Result<Vec<Tag>> {
+    let mut tags = existing_tags
+        .into_iter()
+        .filter(|t| t.key() != STATUS_TAG_KEY && t.key() != DEPLOYED_AT_TAG_KEY)
+        .collect::<Vec<_>>();
+
+    tags.push(
+        Tag::builder()
+            .key(STATUS_TAG_KEY)
+            .value(status.as_str())
+            .build()?,
+    );
+    tags.push(
+        Tag::builder()
+            .key(DEPLOYED_AT_TAG_KEY)
+            .value(deployed_at)
+            .build()?,
     );
 
+    Ok(tags)
+}
+
+async fn tag_source(
+    s3_client: &aws_sdk_s3::Client,
+    record: &Record,
+    status: DeploymentStatus,
+    deployed_at: &str,
+) -> Result<()> {
+    let bucket = &record.s3.bucket.name;
+    let key = &record.s3.object.key;
+
+    debug!("Get Object Tagging: {}:{}", bucket, key);
+    let existing_tags = s3_client
+        .get_object_tagging()
+        .bucket(bucket)
+        .key(key)
+        .send()
+        .await?
+        .tag_set;
+
+    let tags = deployment_tags(existing_tags, status, deployed_at)?;
+
+    debug!("Put Object Tagging: {}:{} {:?}", bucket, key, tags);
+    s3_client
+        .put_object_tagging()
+        .bucket(bucket)
+        .key(key)
+        .tagging(Tagging::builder().set_tag_set(Some(tags)).build()?)
+        .send()
+        .await?;
+
+    info!("Put Object Tagging Succeeded: {}:{}", bucket, key);
+
     Ok(())
 }
 
-pub async fn update(event: Event) -> Result<()> {
+pub async fn update(event: Event, config: &Config) -> Result<()> {
     debug!("Event: {:?}", event);
 
-    let aws_config = ConfigLoader::default()
-        .region(Region::new(get_region(&event.records)?))
-        .load()
-        .await;
-
-    let s3_client = aws_sdk_s3::Client::new(&aws_config);
-    let lambda_client = aws_sdk_lambda::Client::new(&aws_config);
+    let clients = Clients::new(get_region(&event.records)?).await;
 
-    let mut update_code_futures = Vec::with_capacity(event.records.len());
+    let mut deployments = Vec::with_capacity(event.records.len());
 
     for record in event.records {
         debug!("Record: {:?}", record);
 
-        let function_names = get_function_names_from_md(&s3_client, &record).await;
-        let function_names = get_function_names(function_names, &record)?;
-
-        for function_name in function_names.split(',') {
-            update_code_futures.push(tokio::spawn(update_code(
-                lambda_client.clone(),
-                function_name.to_string(),
-                record.s3.bucket.name.clone(),
-                record.s3.object.key.clone(),
-            )));
+        if shutdown::requested() {
+            return Err(anyhow!(
+                "Shutdown requested, not deploying {}:{}",
+                record.s3.bucket.name,
+                record.s3.object.key
+            ));
         }
+
+        deployments.push(deployment(&clients, config, record).await?);
     }
 
-    debug!("{} function(s) to update", update_code_futures.len());
-    try_join_all(update_code_futures).await?;
+    deploy(&clients, config, deployments).await
+}
+
+/// Reads an object's metadata to build its deployment, noting which of its targets require approval.
+async fn deployment(clients: &Clients, config: &Config, record: Record) -> Result<Deployment> {
+    let object_md = get_object_md(&clients.s3, &record).await;
+    schema::validate(&object_md).map_err(|e| {
+        anyhow!(
+            "Invalid metadata of {}:{}: {}",
+            record.s3.bucket.name,
+            record.s3.object.key,
+            e
+        )
+    })?;
+
+    let mut targets = match object_md.get(bundle::MANIFEST_MD_KEY) {
+        Some(manifest_path) => bundle::targets(clients, &record, manifest_path).await?,
+        None => get_function_names(object_md.get(FUNCTION_NAME_MD_KEY), &record, &config.groups)?
+            .split(TIER_SEPARATOR)
+            .zip(0..)
+            .flat_map(|(tier, order)| {
+                tier.split(',').map(move |function_name| Target {
+                    function_name: function_name.to_string(),
+                    code: Code::Object,
+                    order,
+                    requires_approval: false,
+                })
+            })
+            .collect(),
+    };
+
+    for target in &mut targets {
+        target.requires_approval =
+            approval::is_required(clients, config, &target.function_name).await?;
+    }
+
+    Ok(Deployment {
+        order: get_order(object_md.get(ORDER_MD_KEY), &record)?,
+        record,
+        targets,
+        health_check_url: object_md.get(health::URL_MD_KEY).cloned(),
+    })
+}
+
+/// Performs the update described by an approved [ApprovalRequest], once it's been matched to a
+/// pending request LambdUpdate issued, then resumes the rest of its object's deployment. Each
+/// request can only be approved once.
+pub async fn approve(request: ApprovalRequest, config: &Config) -> Result<()> {
+    debug!("Approval: {:?}", request);
+
+    let clients = Clients::new(request.region.clone()).await;
+
+    let request = approval::issued(&clients, config, &request).await?;
+    request.check_expiry(Utc::now())?;
+    function_name::validate(&request.function_name).map_err(|e| {
+        anyhow!(
+            "Invalid function name {:?} in approval request: {}",
+            request.function_name,
+            e
+        )
+    })?;
+
+    approval::check_artifact(&clients, &request).await?;
+
+    // The rest of the object's deployment was deferred from the approved function's stage on, so
+    // resume it there. Functions in that stage which are still awaiting approval defer it again.
+    let record = Record {
+        region: request.region.clone(),
+        s3: (request.bucket.as_str(), request.key.as_str()).into(),
+    };
+    let mut deployment = deployment(&clients, config, record).await?;
+
+    let is_approved = |target: &Target| {
+        target.function_name == request.function_name
+            && target.code.package_path() == request.package.as_deref()
+    };
+    let approved_order = deployment
+        .targets
+        .iter()
+        .find(|target| is_approved(target))
+        .map(|target| target.order)
+        .ok_or_else(|| {
+            anyhow!(
+                "{}:{} no longer updates {}",
+                request.bucket,
+                request.key,
+                request.function_name
+            )
+        })?;
+
+    deployment.targets.retain_mut(|target| {
+        if is_approved(target) {
+            target.requires_approval = false;
+            true
+        } else if target.order == approved_order {
+            target.requires_approval
+        } else {
+            target.order > approved_order
+        }
+    });
+
+    deploy(&clients, config, vec![deployment]).await?;
+
+    approval::consume(&clients, config, &request).await;
 
     Ok(())
 }
 
+/// Advances in-progress rollouts, e.g. on a schedule.
+pub async fn advance_rollouts(request: RolloutRequest, config: &Config) -> Result<()> {
+    debug!("Rollout: {:?}", request);
+
+    let clients = Clients::new(None).await;
+
+    match rollout::Rollouts::new(&clients, config) {
+        Some(rollouts) => rollouts.advance_all().await,
+        None => Err(anyhow!("Rollouts are not configured")),
+    }
+}
+
+/// The targets updated in one stage, and the deployments whose last stage it is.
+#[derive(Default)]
+struct Stage {
+    /// Each target, with the index of the deployment it belongs to.
+    targets: Vec<(usize, Target)>,
+    finishing: Vec<usize>,
+}
+
+/// How a deployment's targets have fared so far.
+#[derive(Default)]
+struct DeploymentProgress {
+    function_updates: Vec<FunctionUpdate>,
+    notifications: Vec<notify::Notification>,
+    failures: usize,
+    skipped: usize,
+    pending_approvals: usize,
+    /// Targets left until approval is granted for one in an earlier stage of the deployment.
+    deferred: usize,
+}
+
+/// Groups deployments' targets into stages by their deployment's `deploy.order`, then by their own
+/// order within it, lowest first. Deployments without targets finish in their order's first stage.
+fn stages(deployments: &mut [Deployment]) -> BTreeMap<(i64, i64), Stage> {
+    let mut stages = BTreeMap::<_, Stage>::new();
+
+    for (index, deployment) in deployments.iter_mut().enumerate() {
+        let targets = mem::take(&mut deployment.targets);
+
+        let last_stage = targets
+            .iter()
+            .map(|target| (deployment.order, target.order))
+            .max()
+            .unwrap_or((deployment.order, 0));
+        stages.entry(last_stage).or_default().finishing.push(index);
+
+        for target in targets {
+            stages
+                .entry((deployment.order, target.order))
+                .or_default()
+                .targets
+                .push((index, target));
+        }
+    }
+
+    stages
+}
+
+/// Deploys each stage once every target in the stages before it has been updated successfully,
+/// finishing each deployment once its last stage is done.
+async fn deploy(
+    clients: &Clients,
+    config: &Config,
+    mut deployments: Vec<Deployment>,
+) -> Result<()> {
+    let updater = Updater::new(clients, config);
+
+    let started_at = Utc::now();
+    let stages = stages(&mut deployments);
+    let mut progress = deployments
+        .iter()
+        .map(|_| DeploymentProgress::default())
+        .collect::<Vec<_>>();
+    let mut notifications = Vec::new();
+    let mut failures = 0;
+    // Deployments awaiting approval in a stage before the current one.
+    let mut pending = HashSet::new();
+
+    for ((order, tier), stage) in stages {
+        if failures > 0 || !pending.is_empty() {
+            for (index, target) in stage.targets {
+                let record = &deployments[index].record;
+                let source = target
+                    .code
+                    .source(&record.s3.bucket.name, &record.s3.object.key);
+
+                let outcome = if failures > 0 {
+                    error!(
+                        "Update Skipped After Earlier Failures: {} <-- {} (order {}, tier {})",
+                        target.function_name, source, order, tier
+                    );
+                    progress[index].skipped += 1;
+                    notify::Outcome::Skipped
+                } else if pending.contains(&index) {
+                    info!(
+                        "Update Deferred Pending Approval: {} <-- {} (order {}, tier {})",
+                        target.function_name, source, order, tier
+                    );
+                    progress[index].deferred += 1;
+                    notify::Outcome::Deferred
+                } else {
+                    error!(
+                        "Update Skipped Pending Approval: {} <-- {} (order {}, tier {})",
+                        target.function_name, source, order, tier
+                    );
+                    progress[index].skipped += 1;
+                    notify::Outcome::Skipped
+                };
+
+                progress[index].notifications.push(notify::Notification {
+                    function_name: target.function_name,
+                    source,
+                    outcome,
+                });
+            }
+        } else {
+            debug!(
+                "Deploy Stage: order {}, tier {} ({} target(s))",
+                order,
+                tier,
+                stage.targets.len()
+            );
+            failures += deploy_stage(
+                clients,
+                config,
+                &updater,
+                &deployments,
+                stage.targets,
+                &mut progress,
+            )
+            .await;
+
+            pending.extend((0..progress.len()).filter(|&i| progress[i].pending_approvals > 0));
+        }
+
+        for index in stage.finishing {
+            failures += finish(
+                clients,
+                config,
+                &updater,
+                &deployments[index],
+                mem::take(&mut progress[index]),
+                &mut notifications,
+            )
+            .await;
+        }
+    }
+
+    notify::send(&clients.sns, config, &notifications).await;
+
+    if config.metrics {
+        observability::put_metrics(
+            &clients.cloudwatch,
+            deployments.len(),
+            failures,
+            Utc::now() - started_at,
+        )
+        .await;
+    }
+
+    if failures > 0 {
+        Err(anyhow!("{} deployment failure(s)", failures))
+    } else {
+        Ok(())
+    }
+}
+
+/// Updates a stage's targets concurrently, requesting approval instead for those which need it.
+/// Returns how many failed.
+async fn deploy_stage(
+    clients: &Clients,
+    config: &Config,
+    updater: &Updater,
+    deployments: &[Deployment],
+    targets: Vec<(usize, Target)>,
+    progress: &mut [DeploymentProgress],
+) -> usize {
+    let mut updates = Vec::with_capacity(targets.len());
+    let mut failures = 0;
+
+    for (index, target) in targets {
+        let record = &deployments[index].record;
+        let bucket = &record.s3.bucket.name;
+        let key = &record.s3.object.key;
+        let source = target.code.source(bucket, key);
+
+        if target.requires_approval {
+            match approval::request(clients, config, record, &target).await {
+                Ok(true) => {
+                    progress[index].pending_approvals += 1;
+                    continue;
+                }
+                // Already approved, e.g. when retrying, so it's updated like any other.
+                Ok(false) => {}
+                Err(e) => {
+                    error!(
+                        "Approval Request Failed: {} <-- {}: {:?}",
+                        target.function_name, source, e
+                    );
+                    progress[index].failures += 1;
+                    progress[index].notifications.push(notify::Notification {
+                        function_name: target.function_name,
+                        source,
+                        outcome: notify::Outcome::Failed(format!("Approval request failed: {}", e)),
+                    });
+                    failures += 1;
+                    continue;
+                }
+            }
+        }
+
+        let function_name = target.function_name.clone();
+        let update = tokio::spawn(
+            updater
+                .clone()
+                .update_code(target, bucket.clone(), key.clone()),
+        );
+        updates.push(((index, function_name, source), update));
+    }
+
+    debug!("{} function(s) to update", updates.len());
+
+    let (sources, updates): (Vec<_>, Vec<_>) = updates.into_iter().unzip();
+
+    for ((index, function_name, source), result) in sources.into_iter().zip(join_all(updates).await)
+    {
+        let outcome = match result.map_err(Error::from).and_then(|r| r) {
+            Ok(function_update) => {
+                let outcome = notify::Outcome::Updated(function_update.version.clone());
+                progress[index].function_updates.push(function_update);
+                outcome
+            }
+            Err(e) => {
+                error!(
+                    "Update Function Code Failed: {} <-- {}: {:?}",
+                    function_name, source, e
+                );
+                progress[index].failures += 1;
+                failures += 1;
+                notify::Outcome::Failed(e.to_string())
+            }
+        };
+
+        progress[index].notifications.push(notify::Notification {
+            function_name,
+            source,
+            outcome,
+        });
+    }
+
+    failures
+}
+
+/// Checks a deployment's health once all of its targets are updated, then reports on it and queues
+/// its notifications. Returns how many failures this adds to those already counted.
+async fn finish(
+    clients: &Clients,
+    config: &Config,
+    updater: &Updater,
+    deployment: &Deployment,
+    mut progress: DeploymentProgress,
+    notifications: &mut Vec<notify::Notification>,
+) -> usize {
+    let record = &deployment.record;
+    let mut failures = 0;
+
+    // Deployments awaiting approval are health checked once they've resumed and completed.
+    if let (0, 0, 0, 0, Some(url)) = (
+        progress.failures,
+        progress.skipped,
+        progress.pending_approvals,
+        progress.deferred,
+        &deployment.health_check_url,
+    ) {
+        if let Err(e) = updater
+            .check_health(url, &progress.function_updates, config)
+            .await
+        {
+            error!(
+                "Health Check Failed: {}:{}: {:?}",
+                record.s3.bucket.name, record.s3.object.key, e
+            );
+            for notification in &mut progress.notifications {
+                notification.outcome =
+                    notify::Outcome::Failed(format!("Health check failed: {}", e));
+            }
+            progress.failures += 1;
+            failures += 1;
+        }
+    }
+
+    let status = if progress.failures > 0 {
+        DeploymentStatus::Failed
+    } else if progress.skipped > 0 {
+        error!(
+            "Deployment Skipped After Earlier Failures or Pending Approvals: {}:{}",
+            record.s3.bucket.name, record.s3.object.key
+        );
+        failures += 1;
+        DeploymentStatus::Failed
+    } else if progress.pending_approvals > 0 || progress.deferred > 0 {
+        DeploymentStatus::PendingApproval
+    } else {
+        DeploymentStatus::Succeeded
+    };
+
+    report(clients, config, record, status, &progress.function_updates).await;
+
+    notifications.append(&mut progress.notifications);
+
+    failures
+}
+
+/// Writes a deployment's marker and tags its source object, if configured. Failures are logged
+/// rather than returned, so that one object's reporting can't hide the rest of the results.
+async fn report(
+    clients: &Clients,
+    config: &Config,
+    record: &Record,
+    status: DeploymentStatus,
+    function_updates: &[FunctionUpdate],
+) {
+    let bucket = &record.s3.bucket.name;
+    let key = &record.s3.object.key;
+    let deployed_at = timestamp();
+
+    if status == DeploymentStatus::Succeeded && config.write_marker {
+        if let Err(e) = write_marker(&clients.s3, record, function_updates, &deployed_at).await {
+            warn!("Put Deployment Marker Failed: {}:{}: {:?}", bucket, key, e);
+        }
+    }
+
+    if config.tag_source {
+        if let Err(e) = tag_source(&clients.s3, record, status, &deployed_at).await {
+            warn!("Tag Source Object Failed: {}:{}: {:?}", bucket, key, e);
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use anyhow::Error;
-    use std::collections::HashMap;
 
     const TEST_EVENT: &str = r#"{"Records":[{"eventVersion":"2.0","eventSource":"aws:s3","awsRegion":"us-west-2","eventTime":"1970-01-01T00:00:00.000Z","eventName":"ObjectCreated:Put","userIdentity":{"principalId":"EXAMPLE"},"requestParameters":{"sourceIPAddress":"127.0.0.1"},"responseElements":{"x-amz-request-id":"EXAMPLE123456789","x-amz-id-2":"EXAMPLE123/5678abcdefghijklambdaisawesome/mnopqrstuvwxyzABCDEFGH"},"s3":{"s3SchemaVersion":"1.0","configurationId":"testConfigRule","bucket":{"name":"my-s3-bucket","ownerIdentity":{"principalId":"EXAMPLE"},"arn":"arn:aws:s3:::example-bucket"},"object":{"key":"HappyFace.jpg","size":1024,"eTag":"0123456789abcdef0123456789abcdef","sequencer":"0A1B2C3D4E5F678901"}}}]}"#;
 
     impl Record {
         fn new(region: &str, bucket: &str, key: &str) -> Self {
             Self {
-                region: region.to_string(),
+                region: Some(region.to_string()),
                 s3: (bucket, key).into(),
             }
         }
     }
 
+    #[test]
+    fn test_env_flag() -> Result<()> {
+        const NAME: &str = "LAMBDUPDATE_TEST_ENV_FLAG";
+
+        env::remove_var(NAME);
+        assert!(!env_flag(NAME)?);
+
+        for (value, expected) in [("", false), (" ", false), ("false", false), ("True", true)] {
+            env::set_var(NAME, value);
+            assert_eq!(expected, env_flag(NAME)?, "{:?}", value);
+        }
+
+        env::set_var(NAME, "yes");
+        assert!(env_flag(NAME).is_err());
+
+        env::remove_var(NAME);
+
+        Ok(())
+    }
+
+    /// The region tests resolve from the environment. Tests which set `AWS_REGION` only ever set
+    /// it to this, so they can run concurrently.
+    const DEFAULT_TEST_REGION: &str = "us-west-2";
+
+    #[tokio::test]
+    async fn test_load_aws_config() {
+        env::set_var("AWS_REGION", DEFAULT_TEST_REGION);
+
+        let aws_config = load_aws_config(None).await;
+        assert_eq!(
+            Some(DEFAULT_TEST_REGION),
+            aws_config.region().map(Region::as_ref)
+        );
+
+        let aws_config = load_aws_config(Some("eu-west-1".to_string())).await;
+        assert_eq!(Some("eu-west-1"), aws_config.region().map(Region::as_ref));
+    }
+
     #[test]
     fn test_deserialize() -> Result<()> {
         let event: Event = serde_json::from_str(TEST_EVENT)?;
@@ -248,22 +1472,40 @@ mod test {
         assert_eq!(1, event.records.len());
 
         let record = &event.records[0];
-        assert_eq!("us-west-2", record.region);
+        assert_eq!(Some("us-west-2"), record.region.as_deref());
         assert_eq!("my-s3-bucket", record.s3.bucket.name);
         assert_eq!("HappyFace.jpg", record.s3.object.key);
 
         Ok(())
     }
 
+    #[test]
+    fn test_deserialize_request() -> Result<()> {
+        let request: Request = serde_json::from_str(TEST_EVENT)?;
+        assert!(matches!(request, Request::Update(_)));
+
+        let request: Request = serde_json::from_str(
+            r#"{"approval":{"token":"abc","region":"us-east-1","function_name":"foo","bucket":"bucket","key":"foo.zip","e_tag":null,"requested_at":"1970-01-01T00:00:00Z","expires_at":"1970-01-02T00:00:00Z"}}"#,
+        )?;
+        assert!(
+            matches!(request, Request::Approval { approval } if approval.function_name == "foo")
+        );
+
+        let request: Request = serde_json::from_str(r#"{"rollout":{}}"#)?;
+        assert!(matches!(request, Request::Rollout { .. }));
+
+        Ok(())
+    }
+
     #[test]
     fn test_get_region() -> Result<()> {
         let mut records = vec![Record::new("us-east-1", "foo", "bar")];
 
-        assert_eq!("us-east-1", get_region(&records)?);
+        assert_eq!(Some("us-east-1"), get_region(&records)?.as_deref());
 
         records.push(Record::new("us-east-1", "baz", "quux"));
 
-        assert_eq!("us-east-1", get_region(&records)?);
+        assert_eq!(Some("us-east-1"), get_region(&records)?.as_deref());
 
         Ok(())
     }
@@ -279,6 +1521,38 @@ mod test {
         }
     }
 
+    #[tokio::test]
+    async fn test_get_region_missing() -> Result<()> {
+        env::set_var("AWS_REGION", DEFAULT_TEST_REGION);
+
+        let event: Event = serde_json::from_str(
+            r#"{"Records":[{"s3":{"bucket":{"name":"foo"},"object":{"key":"bar.zip"}}}]}"#,
+        )?;
+
+        let region = get_region(&event.records)?;
+        assert_eq!(None, region);
+
+        // Clients for the records fall back to the default provider chain's region.
+        let aws_config = load_aws_config(region).await;
+        assert_eq!(
+            Some(DEFAULT_TEST_REGION),
+            aws_config.region().map(Region::as_ref)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_region_mixed() {
+        let mut records = vec![Record::new("us-east-1", "foo", "bar")];
+        records.push(Record {
+            region: None,
+            s3: ("baz", "quux").into(),
+        });
+
+        assert!(get_region(&records).is_err());
+    }
+
     #[test]
     fn test_get_region_multiple() {
         let records = vec![
@@ -295,8 +1569,11 @@ mod test {
 
     #[test]
     fn test_get_function_names_from_md() -> Result<()> {
-        let function_names =
-            get_function_names(Some("foo,bar"), &Record::new("us-east-1", "foo", "bar"))?;
+        let function_names = get_function_names(
+            Some("foo,bar"),
+            &Record::new("us-east-1", "foo", "bar"),
+            &BTreeMap::new(),
+        )?;
 
         assert_eq!("foo,bar", function_names);
 
@@ -305,8 +1582,11 @@ mod test {
 
     #[test]
     fn test_get_function_names_from_key() -> Result<()> {
-        let function_names =
-            get_function_names(None::<&str>, &Record::new("us-east-1", "foo", "bar.zip"))?;
+        let function_names = get_function_names(
+            None::<&str>,
+            &Record::new("us-east-1", "foo", "bar.zip"),
+            &BTreeMap::new(),
+        )?;
 
         assert_eq!("bar", function_names);
 
@@ -315,7 +1595,11 @@ mod test {
 
     #[test]
     fn test_get_function_names_from_unzipped_key() {
-        let res = get_function_names(None::<&str>, &Record::new("us-east-1", "foo", "bar"));
+        let res = get_function_names(
+            None::<&str>,
+            &Record::new("us-east-1", "foo", "bar"),
+            &BTreeMap::new(),
+        );
 
         assert!(res.is_err());
         if let Err(e) = res {
@@ -323,6 +1607,137 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_get_function_names_from_group() -> Result<()> {
+        let record = Record::new("us-east-1", "foo", "bar.zip");
+        let groups = BTreeMap::from([(
+            "checkout".to_string(),
+            vec!["orders-api".to_string(), "payments-worker".to_string()],
+        )]);
+
+        assert_eq!(
+            "orders-api,payments-worker,email-sender",
+            get_function_names(Some("group:checkout,email-sender"), &record, &groups)?
+        );
+
+        let res = get_function_names(Some("group:shipping"), &record, &groups);
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert!(e.to_string().contains("Unknown group: shipping"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_function_names_tiers() -> Result<()> {
+        let record = Record::new("us-east-1", "foo", "bar.zip");
+        let groups = BTreeMap::from([(
+            "checkout".to_string(),
+            vec!["orders-api".to_string(), "payments-worker".to_string()],
+        )]);
+
+        assert_eq!(
+            "db-migrator;orders-api,payments-worker;email-sender",
+            get_function_names(
+                Some("db-migrator;group:checkout;email-sender,orders-api"),
+                &record,
+                &groups
+            )?
+        );
+        assert!(get_function_names(Some("foo;;bar"), &record, &groups).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_function_names_invalid() {
+        let record = Record::new("us-east-1", "foo", "bar baz.zip");
+
+        let res = get_function_names(Some("foo,"), &record, &BTreeMap::new());
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert!(e.to_string().contains("'function.names' metadata"));
+        }
+
+        let res = get_function_names(None::<&str>, &record, &BTreeMap::new());
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert!(e.to_string().contains("from object key foo:bar baz.zip"));
+        }
+    }
+
+    #[test]
+    fn test_get_order() -> Result<()> {
+        let record = Record::new("us-east-1", "foo", "bar.zip");
+
+        assert_eq!(0, get_order(None, &record)?);
+        assert_eq!(-1, get_order(Some(&" -1 ".to_string()), &record)?);
+
+        let res = get_order(Some(&"first".to_string()), &record);
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert!(e
+                .to_string()
+                .contains("'deploy.order' metadata of foo:bar.zip"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stages() {
+        let target = |function_name: &str, order| Target {
+            function_name: function_name.to_string(),
+            code: Code::Object,
+            order,
+            requires_approval: false,
+        };
+        let deployment = |key: &str, order, targets| Deployment {
+            record: Record::new("us-east-1", "foo", key),
+            targets,
+            health_check_url: None,
+            order,
+        };
+
+        let mut deployments = vec![
+            deployment("producer.zip", 1, vec![target("producer", 0)]),
+            deployment(
+                "consumer.zip",
+                0,
+                vec![target("consumer-api", 1), target("consumer-db", 0)],
+            ),
+            deployment("worker.zip", 1, vec![target("worker", 0)]),
+            deployment("unchanged.zip", 0, Vec::new()),
+        ];
+
+        let stages = stages(&mut deployments)
+            .into_iter()
+            .map(|(order, stage)| {
+                let function_names = stage
+                    .targets
+                    .into_iter()
+                    .map(|(_, target)| target.function_name)
+                    .collect::<Vec<_>>();
+                (order, function_names, stage.finishing)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                ((0, 0), vec!["consumer-db".to_string()], vec![3]),
+                ((0, 1), vec!["consumer-api".to_string()], vec![1]),
+                (
+                    (1, 0),
+                    vec!["producer".to_string(), "worker".to_string()],
+                    vec![0, 2]
+                ),
+            ],
+            stages
+        );
+        assert!(deployments.iter().all(|d| d.targets.is_empty()));
+    }
+
     #[test]
     fn test_get_function_names_from_head_object_output() {
         let fn_names = "foo,bar";
@@ -331,8 +1746,9 @@ mod test {
             .metadata(FUNCTION_NAME_MD_KEY, fn_names)
             .build());
 
-        let fn_names_from_output =
-            get_function_names_from_head_object_output(output, "bucket", "key");
+        let fn_names_from_output = get_object_md_from_head_object_output(output, "bucket", "key")
+            .get(FUNCTION_NAME_MD_KEY)
+            .cloned();
 
         assert!(fn_names_from_output.is_some());
         if let Some(fn_names_from_output) = fn_names_from_output {
@@ -344,8 +1760,9 @@ mod test {
     fn test_get_function_names_from_head_object_output_err() {
         let output: Result<HeadObjectOutput, Error> = Err(anyhow!("Error!"));
 
-        let fn_names_from_output =
-            get_function_names_from_head_object_output(output, "bucket", "key");
+        let fn_names_from_output = get_object_md_from_head_object_output(output, "bucket", "key")
+            .get(FUNCTION_NAME_MD_KEY)
+            .cloned();
 
         assert!(fn_names_from_output.is_none());
     }
@@ -354,8 +1771,9 @@ mod test {
     fn test_get_function_names_from_head_object_output_no_metadata() {
         let output: Result<HeadObjectOutput, Error> = Ok(HeadObjectOutput::builder().build());
 
-        let fn_names_from_output =
-            get_function_names_from_head_object_output(output, "bucket", "key");
+        let fn_names_from_output = get_object_md_from_head_object_output(output, "bucket", "key")
+            .get(FUNCTION_NAME_MD_KEY)
+            .cloned();
 
         assert!(fn_names_from_output.is_none());
     }
@@ -366,9 +1784,90 @@ mod test {
             .set_metadata(Some(HashMap::new()))
             .build());
 
-        let fn_names_from_output =
-            get_function_names_from_head_object_output(output, "bucket", "key");
+        let fn_names_from_output = get_object_md_from_head_object_output(output, "bucket", "key")
+            .get(FUNCTION_NAME_MD_KEY)
+            .cloned();
 
         assert!(fn_names_from_output.is_none());
     }
+
+    #[test]
+    fn test_marker_key() {
+        assert_eq!("foo.zip.deployed.json", marker_key("foo.zip"));
+        assert_eq!("a/b/foo.zip.deployed.json", marker_key("a/b/foo.zip"));
+    }
+
+    #[test]
+    fn test_marker_body() -> Result<()> {
+        let record = Record::new("us-east-1", "bucket", "foo.zip");
+        let function_updates = vec![
+            FunctionUpdate {
+                function_name: "foo".to_string(),
+                package: None,
+                version: Some("$LATEST".to_string()),
+                code_sha256: Some("abc=".to_string()),
+            },
+            FunctionUpdate {
+                function_name: "bar".to_string(),
+                package: Some("bar.zip".to_string()),
+                version: Some("2".to_string()),
+                code_sha256: None,
+            },
+        ];
+
+        let body: serde_json::Value =
+            serde_json::from_slice(&marker_body(&record, &function_updates, "now")?)?;
+
+        assert_eq!("bucket", body["bucket"]);
+        assert_eq!("foo.zip", body["key"]);
+        assert_eq!("now", body["deployed_at"]);
+        assert_eq!("foo", body["functions"][0]["function_name"]);
+        assert_eq!("$LATEST", body["functions"][0]["version"]);
+        assert_eq!("bar", body["functions"][1]["function_name"]);
+        assert_eq!("bar.zip", body["functions"][1]["package"]);
+        assert_eq!("2", body["functions"][1]["version"]);
+
+        Ok(())
+    }
+
+    fn tag_map(tags: &[Tag]) -> HashMap<&str, &str> {
+        tags.iter().map(|t| (t.key(), t.value())).collect()
+    }
+
+    #[test]
+    fn test_deployment_tags() -> Result<()> {
+        let tags = deployment_tags(Vec::new(), DeploymentStatus::Succeeded, "now")?;
+
+        let tags = tag_map(&tags);
+        assert_eq!(2, tags.len());
+        assert_eq!(Some(&"succeeded"), tags.get(STATUS_TAG_KEY));
+        assert_eq!(Some(&"now"), tags.get(DEPLOYED_AT_TAG_KEY));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deployment_tags_merges_existing() -> Result<()> {
+        let existing_tags = vec![
+            Tag::builder().key("team").value("payments").build()?,
+            Tag::builder()
+                .key(STATUS_TAG_KEY)
+                .value("succeeded")
+                .build()?,
+            Tag::builder()
+                .key(DEPLOYED_AT_TAG_KEY)
+                .value("then")
+                .build()?,
+        ];
+
+        let tags = deployment_tags(existing_tags, DeploymentStatus::Failed, "now")?;
+
+        let tags = tag_map(&tags);
+        assert_eq!(3, tags.len());
+        assert_eq!(Some(&"payments"), tags.get("team"));
+        assert_eq!(Some(&"failed"), tags.get(STATUS_TAG_KEY));
+        assert_eq!(Some(&"now"), tags.get(DEPLOYED_AT_TAG_KEY));
+
+        Ok(())
+    }
 }
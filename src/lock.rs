@@ -0,0 +1,189 @@
+use crate::{rollout, Clients, Config};
+use anyhow::{anyhow, Result};
+use aws_sdk_dynamodb::types::AttributeValue;
+use chrono::{Duration, Utc};
+use log::{debug, info, warn};
+use uuid::Uuid;
+
+pub(crate) const TABLE_ENV_VAR: &str = "LAMBDUPDATE_LOCK_TABLE";
+
+pub(crate) const WAIT_SECS_ENV_VAR: &str = "LAMBDUPDATE_LOCK_WAIT_SECS";
+
+pub(crate) const DEFAULT_WAIT: Duration = Duration::seconds(20);
+
+/// Allowance in a lock's lease for the calls made while it's held, on top of their waits.
+const LEASE_MARGIN: Duration = Duration::minutes(1);
+
+const INITIAL_BACKOFF: Duration = Duration::milliseconds(250);
+
+const MAX_BACKOFF: Duration = Duration::seconds(4);
+
+const FUNCTION_NAME_ATTR: &str = "function_name";
+
+const OWNER_ATTR: &str = "owner";
+
+const EXPIRES_AT_ATTR: &str = "expires_at";
+
+/// Per-function locks, held in a DynamoDB table keyed by function name, which keep concurrent
+/// invocations from updating the same function at the same time.
+#[derive(Debug, Clone)]
+pub(crate) struct Locks {
+    client: aws_sdk_dynamodb::Client,
+    table: String,
+    owner: String,
+    wait: Duration,
+    lease: Duration,
+}
+
+pub(crate) struct Lock {
+    locks: Locks,
+    function_name: String,
+}
+
+/// How long a lock is held before it's considered abandoned, e.g. if its holder crashed. This covers
+/// the longest an update can take while holding it: waiting for its shadow to update and smoke
+/// testing it (up to the health check timeout each), then updating it and waiting to publish a
+/// version for its rollout.
+fn lease(config: &Config) -> Duration {
+    config.health_check_timeout * 2 + rollout::UPDATE_WAIT + LEASE_MARGIN
+}
+
+fn next_backoff(backoff: Duration) -> Duration {
+    (backoff * 2).min(MAX_BACKOFF)
+}
+
+impl Locks {
+    pub(crate) fn new(clients: &Clients, config: &Config) -> Option<Self> {
+        config.lock_table.as_ref().map(|table| Self {
+            client: clients.dynamodb.clone(),
+            table: table.clone(),
+            owner: Uuid::new_v4().to_string(),
+            wait: config.lock_wait,
+            lease: lease(config),
+        })
+    }
+
+    async fn try_acquire(&self, function_name: &str) -> Result<bool> {
+        let now = Utc::now().timestamp();
+
+        let result = self
+            .client
+            .put_item()
+            .table_name(&self.table)
+            .item(
+                FUNCTION_NAME_ATTR,
+                AttributeValue::S(function_name.to_string()),
+            )
+            .item(OWNER_ATTR, AttributeValue::S(self.owner.clone()))
+            .item(
+                EXPIRES_AT_ATTR,
+                AttributeValue::N((now + self.lease.num_seconds()).to_string()),
+            )
+            .condition_expression("attribute_not_exists(#f) OR #e < :now")
+            .expression_attribute_names("#f", FUNCTION_NAME_ATTR)
+            .expression_attribute_names("#e", EXPIRES_AT_ATTR)
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(e)
+                if e.as_service_error()
+                    .is_some_and(|e| e.is_conditional_check_failed_exception()) =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Acquires the lock for a function, waiting for another holder to release it if necessary.
+    pub(crate) async fn acquire(&self, function_name: &str) -> Result<Lock> {
+        let deadline = Utc::now() + self.wait;
+        let mut backoff = INITIAL_BACKOFF;
+
+        loop {
+            debug!("Acquire Lock: {} ({})", function_name, self.owner);
+            if self.try_acquire(function_name).await? {
+                info!("Acquire Lock Succeeded: {}", function_name);
+                return Ok(Lock {
+                    locks: self.clone(),
+                    function_name: function_name.to_string(),
+                });
+            }
+
+            if Utc::now() + backoff > deadline {
+                return Err(anyhow!(
+                    "Timed out after {}s waiting for lock on {}",
+                    self.wait.num_seconds(),
+                    function_name
+                ));
+            }
+
+            debug!(
+                "Lock Held, Retrying in {}ms: {}",
+                backoff.num_milliseconds(),
+                function_name
+            );
+            tokio::time::sleep(backoff.to_std()?).await;
+            backoff = next_backoff(backoff);
+        }
+    }
+}
+
+impl Lock {
+    /// Releases the lock. Failures are logged rather than returned, since the lock will expire.
+    pub(crate) async fn release(self) {
+        debug!("Release Lock: {}", self.function_name);
+
+        let result = self
+            .locks
+            .client
+            .delete_item()
+            .table_name(&self.locks.table)
+            .key(
+                FUNCTION_NAME_ATTR,
+                AttributeValue::S(self.function_name.clone()),
+            )
+            .condition_expression("#o = :owner")
+            .expression_attribute_names("#o", OWNER_ATTR)
+            .expression_attribute_values(":owner", AttributeValue::S(self.locks.owner.clone()))
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => info!("Release Lock Succeeded: {}", self.function_name),
+            Err(e) => warn!("Release Lock Failed: {}: {:?}", self.function_name, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lease() {
+        assert_eq!(Duration::seconds(165), lease(&Config::default()));
+
+        let config = Config {
+            health_check_timeout: Duration::minutes(5),
+            ..Default::default()
+        };
+        assert_eq!(Duration::seconds(705), lease(&config));
+    }
+
+    #[test]
+    fn test_next_backoff() {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut backoffs = Vec::new();
+
+        for _ in 0..7 {
+            backoffs.push(backoff.num_milliseconds());
+            backoff = next_backoff(backoff);
+        }
+
+        assert_eq!(vec![250, 500, 1000, 2000, 4000, 4000, 4000], backoffs);
+    }
+}
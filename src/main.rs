@@ -1,24 +1,54 @@
 use anyhow::Result;
 use clap::{Arg, ArgAction, Command};
-use lambdupdate::{set_up_logger, update, Event, Record};
+use lambdupdate::{
+    listen_for_shutdown, run_until_shutdown, set_up_logger, setup_observability, tui, update,
+    Config, Event, Record,
+};
 use log::debug;
 
 #[derive(Debug)]
 struct Args {
     verbose: bool,
-    region: String,
+    region: Option<String>,
     bucket: String,
     key: String,
+    write_marker: bool,
+    tag_source: bool,
+    config: Option<String>,
 }
 
-fn parse_args() -> Args {
+#[derive(Debug)]
+struct ObservabilityArgs {
+    verbose: bool,
+    region: Option<String>,
+    function_name: String,
+    alarm_topic_arn: Option<String>,
+}
+
+#[derive(Debug)]
+struct TuiArgs {
+    region: Option<String>,
+    bucket: String,
+    config: Option<String>,
+}
+
+#[derive(Debug)]
+enum Action {
+    Update(Args),
+    SetupObservability(ObservabilityArgs),
+    Tui(TuiArgs),
+}
+
+fn parse_args() -> Action {
     let matches = Command::new("LambdUpdate")
         .version("0.1")
         .author("Jacob Luszcz")
+        .subcommand_negates_reqs(true)
         .arg(
             Arg::new("verbose")
                 .short('v')
                 .long("verbose")
+                .global(true)
                 .action(ArgAction::SetTrue)
                 .help("Verbose mode. Outputs DEBUG and higher log messages."),
         )
@@ -26,8 +56,8 @@ fn parse_args() -> Args {
             Arg::new("region")
                 .short('r')
                 .long("region")
-                .required(true)
-                .help("AWS region."),
+                .global(true)
+                .help("AWS region. Defaults to the default provider chain's region."),
         )
         .arg(
             Arg::new("bucket")
@@ -43,14 +73,78 @@ fn parse_args() -> Args {
                 .required(true)
                 .help("S3 key name."),
         )
+        .arg(
+            Arg::new("write-marker")
+                .long("write-marker")
+                .action(ArgAction::SetTrue)
+                .help("Write a <key>.deployed.json marker object after a successful deployment."),
+        )
+        .arg(
+            Arg::new("tag-source")
+                .long("tag-source")
+                .action(ArgAction::SetTrue)
+                .help("Tag the source object with the deployment result."),
+        )
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .help("Config file defining named groups of functions."),
+        )
+        .subcommand(
+            Command::new("setup-observability")
+                .about("Create a CloudWatch dashboard and alarms for LambdUpdate's metrics.")
+                .arg(
+                    Arg::new("function-name")
+                        .long("function-name")
+                        .default_value("lambdupdate")
+                        .help("LambdUpdate's Lambda function name."),
+                )
+                .arg(
+                    Arg::new("alarm-topic-arn")
+                        .long("alarm-topic-arn")
+                        .help("SNS topic ARN to notify when an alarm fires."),
+                ),
+        )
+        .subcommand(
+            Command::new("tui")
+                .about("Show a dashboard of a bucket's functions, deployments, and rollouts.")
+                .arg(
+                    Arg::new("bucket")
+                        .short('b')
+                        .long("bucket")
+                        .required(true)
+                        .help("S3 bucket name."),
+                )
+                .arg(
+                    Arg::new("config")
+                        .short('c')
+                        .long("config")
+                        .help("Config file defining named groups of functions."),
+                ),
+        )
         .get_matches();
 
     let verbose = matches.get_flag("verbose");
 
-    let region = matches
-        .get_one::<String>("region")
-        .map(|l| l.into())
-        .unwrap();
+    let region = matches.get_one::<String>("region").cloned();
+
+    if let Some(matches) = matches.subcommand_matches("setup-observability") {
+        return Action::SetupObservability(ObservabilityArgs {
+            verbose: matches.get_flag("verbose"),
+            region: matches.get_one::<String>("region").cloned(),
+            function_name: matches.get_one::<String>("function-name").cloned().unwrap(),
+            alarm_topic_arn: matches.get_one::<String>("alarm-topic-arn").cloned(),
+        });
+    }
+
+    if let Some(matches) = matches.subcommand_matches("tui") {
+        return Action::Tui(TuiArgs {
+            region: matches.get_one::<String>("region").cloned(),
+            bucket: matches.get_one::<String>("bucket").cloned().unwrap(),
+            config: matches.get_one::<String>("config").cloned(),
+        });
+    }
 
     let bucket = matches
         .get_one::<String>("bucket")
@@ -59,11 +153,39 @@ fn parse_args() -> Args {
 
     let key = matches.get_one::<String>("key").map(|l| l.into()).unwrap();
 
-    Args {
+    let write_marker = matches.get_flag("write-marker");
+
+    let tag_source = matches.get_flag("tag-source");
+
+    let config = matches.get_one::<String>("config").cloned();
+
+    Action::Update(Args {
         verbose,
         region,
         bucket,
         key,
+        write_marker,
+        tag_source,
+        config,
+    })
+}
+
+/// Configures a local update like the function, from `LAMBDUPDATE_*` environment variables, with
+/// flags enabling features on top.
+impl TryFrom<&Args> for Config {
+    type Error = anyhow::Error;
+
+    fn try_from(args: &Args) -> Result<Self> {
+        let mut config = Config::from_env()?;
+
+        if args.write_marker {
+            config.write_marker = true;
+        }
+        if args.tag_source {
+            config.tag_source = true;
+        }
+
+        Ok(config)
     }
 }
 
@@ -80,11 +202,40 @@ impl From<Args> for Event {
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = parse_args();
-    set_up_logger(module_path!(), args.verbose)?;
-    debug!("Args: {:?}", args);
+    match parse_args() {
+        Action::Update(args) => {
+            set_up_logger(module_path!(), args.verbose)?;
+            debug!("Args: {:?}", args);
+            listen_for_shutdown()?;
+
+            let mut config = Config::try_from(&args)?;
+            if let Some(path) = &args.config {
+                config.load_file(path)?;
+            }
 
-    update(args.into()).await?;
+            run_until_shutdown(update(args.into(), &config), config.shutdown_budget).await?;
+        }
+        Action::SetupObservability(args) => {
+            set_up_logger(module_path!(), args.verbose)?;
+            debug!("Args: {:?}", args);
+
+            setup_observability(
+                args.region,
+                &args.function_name,
+                args.alarm_topic_arn.as_deref(),
+            )
+            .await?;
+        }
+        Action::Tui(args) => {
+            // No logger, since log lines would draw over the dashboard.
+            let mut config = Config::from_env()?;
+            if let Some(path) = &args.config {
+                config.load_file(path)?;
+            }
+
+            tui(&args.bucket, args.region, &config).await?;
+        }
+    }
 
     Ok(())
 }
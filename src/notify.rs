@@ -0,0 +1,211 @@
+use crate::Config;
+use log::{debug, info, warn};
+
+pub(crate) const TOPIC_ARN_ENV_VAR: &str = "LAMBDUPDATE_NOTIFICATION_TOPIC_ARN";
+
+pub(crate) const DIGEST_ENV_VAR: &str = "LAMBDUPDATE_NOTIFICATION_DIGEST";
+
+const MAX_SUBJECT_LEN: usize = 100;
+
+#[derive(Debug)]
+pub(crate) enum Outcome {
+    /// Updated, to the given version if one was published.
+    Updated(Option<String>),
+    Failed(String),
+    /// Not attempted because an earlier stage failed, or is awaiting approval for another deployment.
+    Skipped,
+    /// Not attempted until approval is granted for an earlier stage of the same deployment.
+    Deferred,
+}
+
+/// The result of updating one function.
+#[derive(Debug)]
+pub(crate) struct Notification {
+    pub(crate) function_name: String,
+    pub(crate) source: String,
+    pub(crate) outcome: Outcome,
+}
+
+impl Notification {
+    fn verb(&self) -> &'static str {
+        match self.outcome {
+            Outcome::Updated(_) => "updated",
+            Outcome::Failed(_) => "failed",
+            Outcome::Skipped => "skipped",
+            Outcome::Deferred => "deferred",
+        }
+    }
+
+    fn line(&self) -> String {
+        match &self.outcome {
+            Outcome::Updated(Some(version)) => format!(
+                "Updated {} <-- {} (version {})",
+                self.function_name, self.source, version
+            ),
+            Outcome::Updated(None) => format!("Updated {} <-- {}", self.function_name, self.source),
+            Outcome::Failed(e) => {
+                format!("Failed {} <-- {}: {}", self.function_name, self.source, e)
+            }
+            Outcome::Skipped => format!("Skipped {} <-- {}", self.function_name, self.source),
+            Outcome::Deferred => format!(
+                "Deferred {} <-- {} until approval",
+                self.function_name, self.source
+            ),
+        }
+    }
+
+    fn subject(&self) -> String {
+        subject(format!(
+            "LambdUpdate {}: {}",
+            self.verb(),
+            self.function_name
+        ))
+    }
+}
+
+fn subject(subject: String) -> String {
+    subject.chars().take(MAX_SUBJECT_LEN).collect()
+}
+
+/// Summarizes all of an invocation's results, e.g. `LambdUpdate: 3 updated, 1 failed`, followed by
+/// a line per function.
+fn digest(notifications: &[Notification]) -> (String, String) {
+    let counts = ["updated", "failed", "skipped", "deferred"]
+        .into_iter()
+        .map(|verb| {
+            let count = notifications.iter().filter(|n| n.verb() == verb).count();
+            (count, verb)
+        })
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, verb)| format!("{} {}", count, verb))
+        .collect::<Vec<_>>();
+
+    let message = notifications
+        .iter()
+        .map(Notification::line)
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    (
+        subject(format!("LambdUpdate: {}", counts.join(", "))),
+        message,
+    )
+}
+
+async fn publish(
+    sns_client: &aws_sdk_sns::Client,
+    topic_arn: &str,
+    subject: String,
+    message: String,
+) {
+    debug!("Publish Notification: {} {}", topic_arn, subject);
+    let result = sns_client
+        .publish()
+        .topic_arn(topic_arn)
+        .subject(&subject)
+        .message(message)
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => info!("Publish Notification Succeeded: {}", subject),
+        Err(e) => warn!("Publish Notification Failed: {}: {:?}", subject, e),
+    }
+}
+
+/// Publishes notifications to the notification topic, if configured: one per function, or a
+/// single digest in digest mode. Failures are logged rather than returned, since the deployment
+/// itself is already done.
+pub(crate) async fn send(
+    sns_client: &aws_sdk_sns::Client,
+    config: &Config,
+    notifications: &[Notification],
+) {
+    let topic_arn = match &config.notification_topic_arn {
+        Some(topic_arn) if !notifications.is_empty() => topic_arn,
+        _ => return,
+    };
+
+    if config.notification_digest {
+        let (subject, message) = digest(notifications);
+        publish(sns_client, topic_arn, subject, message).await;
+    } else {
+        for notification in notifications {
+            publish(
+                sns_client,
+                topic_arn,
+                notification.subject(),
+                notification.line(),
+            )
+            .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn notification(function_name: &str, outcome: Outcome) -> Notification {
+        Notification {
+            function_name: function_name.to_string(),
+            source: "bucket:foo.zip".to_string(),
+            outcome,
+        }
+    }
+
+    #[test]
+    fn test_line() {
+        assert_eq!(
+            "Updated foo <-- bucket:foo.zip (version 7)",
+            notification("foo", Outcome::Updated(Some("7".to_string()))).line()
+        );
+        assert_eq!(
+            "Updated foo <-- bucket:foo.zip",
+            notification("foo", Outcome::Updated(None)).line()
+        );
+        assert_eq!(
+            "Failed foo <-- bucket:foo.zip: throttled",
+            notification("foo", Outcome::Failed("throttled".to_string())).line()
+        );
+        assert_eq!(
+            "Skipped foo <-- bucket:foo.zip",
+            notification("foo", Outcome::Skipped).line()
+        );
+        assert_eq!(
+            "Deferred foo <-- bucket:foo.zip until approval",
+            notification("foo", Outcome::Deferred).line()
+        );
+    }
+
+    #[test]
+    fn test_subject() {
+        assert_eq!(
+            "LambdUpdate failed: foo",
+            notification("foo", Outcome::Failed("throttled".to_string())).subject()
+        );
+        assert_eq!(
+            MAX_SUBJECT_LEN,
+            notification(&"f".repeat(MAX_SUBJECT_LEN), Outcome::Skipped)
+                .subject()
+                .len()
+        );
+    }
+
+    #[test]
+    fn test_digest() {
+        let (subject, message) = digest(&[
+            notification("foo", Outcome::Updated(None)),
+            notification("bar", Outcome::Failed("throttled".to_string())),
+            notification("baz", Outcome::Updated(None)),
+        ]);
+
+        assert_eq!("LambdUpdate: 2 updated, 1 failed", subject);
+        assert_eq!(
+            "Updated foo <-- bucket:foo.zip\n\
+             Failed bar <-- bucket:foo.zip: throttled\n\
+             Updated baz <-- bucket:foo.zip",
+            message
+        );
+    }
+}
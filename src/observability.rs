@@ -0,0 +1,240 @@
+use crate::load_aws_config;
+use anyhow::{anyhow, Result};
+use aws_sdk_cloudwatch::types::{
+    ComparisonOperator, Dimension, MetricDatum, StandardUnit, Statistic,
+};
+use chrono::Duration;
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+
+pub(crate) const METRICS_ENV_VAR: &str = "LAMBDUPDATE_METRICS";
+
+/// The CloudWatch namespace LambdUpdate's own metrics are published to.
+const NAMESPACE: &str = "LambdUpdate";
+
+const DEPLOYMENTS_METRIC: &str = "Deployments";
+
+const FAILURES_METRIC: &str = "DeploymentFailures";
+
+const DURATION_METRIC: &str = "DeploymentDuration";
+
+/// The name given to the dashboard, and the prefix given to alarms.
+const NAME: &str = "lambdupdate";
+
+const PERIOD: Duration = Duration::minutes(5);
+
+/// Three quarters of the function's timeout, leaving time to notice slow deployments before they
+/// start timing out.
+const MAX_DURATION: Duration = Duration::seconds(225);
+
+/// Publishes an invocation's deployment metrics. Failures are logged rather than returned, so
+/// that monitoring can't fail a deployment.
+pub(crate) async fn put_metrics(
+    cloudwatch_client: &aws_sdk_cloudwatch::Client,
+    deployments: usize,
+    failures: usize,
+    duration: Duration,
+) {
+    let datum = |name: &str, value: f64, unit: StandardUnit| {
+        MetricDatum::builder()
+            .metric_name(name)
+            .value(value)
+            .unit(unit)
+            .build()
+    };
+
+    debug!(
+        "Put Metric Data: {} deployment(s), {} failure(s), {}ms",
+        deployments,
+        failures,
+        duration.num_milliseconds()
+    );
+    let result = cloudwatch_client
+        .put_metric_data()
+        .namespace(NAMESPACE)
+        .metric_data(datum(
+            DEPLOYMENTS_METRIC,
+            deployments as f64,
+            StandardUnit::Count,
+        ))
+        .metric_data(datum(FAILURES_METRIC, failures as f64, StandardUnit::Count))
+        .metric_data(datum(
+            DURATION_METRIC,
+            duration.num_milliseconds() as f64,
+            StandardUnit::Milliseconds,
+        ))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => info!("Put Metric Data Succeeded: {}", NAMESPACE),
+        Err(e) => warn!("Put Metric Data Failed: {}: {:?}", NAMESPACE, e),
+    }
+}
+
+#[derive(Debug)]
+struct Alarm {
+    name: String,
+    namespace: &'static str,
+    metric_name: &'static str,
+    statistic: Statistic,
+    threshold: f64,
+    function_name: Option<String>,
+}
+
+/// Alarms on failed deployments, slow deployments, and throttling of LambdUpdate's own function.
+fn alarms(function_name: &str) -> Vec<Alarm> {
+    vec![
+        Alarm {
+            name: format!("{}-deployment-failures", NAME),
+            namespace: NAMESPACE,
+            metric_name: FAILURES_METRIC,
+            statistic: Statistic::Sum,
+            threshold: 1.0,
+            function_name: None,
+        },
+        Alarm {
+            name: format!("{}-deployment-duration", NAME),
+            namespace: NAMESPACE,
+            metric_name: DURATION_METRIC,
+            statistic: Statistic::Maximum,
+            threshold: MAX_DURATION.num_milliseconds() as f64,
+            function_name: None,
+        },
+        Alarm {
+            name: format!("{}-throttles", NAME),
+            namespace: "AWS/Lambda",
+            metric_name: "Throttles",
+            statistic: Statistic::Sum,
+            threshold: 1.0,
+            function_name: Some(function_name.to_string()),
+        },
+    ]
+}
+
+fn dashboard_body(region: &str, function_name: &str) -> Value {
+    let widget = |x: u32, y: u32, title: &str, metrics: Value| {
+        json!({
+            "type": "metric",
+            "x": x,
+            "y": y,
+            "width": 12,
+            "height": 6,
+            "properties": {
+                "title": title,
+                "region": region,
+                "period": PERIOD.num_seconds(),
+                "view": "timeSeries",
+                "metrics": metrics,
+            },
+        })
+    };
+
+    json!({
+        "widgets": [
+            widget(0, 0, "Deployments", json!([
+                [NAMESPACE, DEPLOYMENTS_METRIC, {"stat": "Sum"}],
+                [NAMESPACE, FAILURES_METRIC, {"stat": "Sum"}],
+            ])),
+            widget(12, 0, "Deployment Duration (ms)", json!([
+                [NAMESPACE, DURATION_METRIC, {"stat": "Average"}],
+                [NAMESPACE, DURATION_METRIC, {"stat": "Maximum"}],
+            ])),
+            widget(0, 6, "Invocations", json!([
+                ["AWS/Lambda", "Invocations", "FunctionName", function_name, {"stat": "Sum"}],
+                ["AWS/Lambda", "Errors", "FunctionName", function_name, {"stat": "Sum"}],
+                ["AWS/Lambda", "Throttles", "FunctionName", function_name, {"stat": "Sum"}],
+            ])),
+            widget(12, 6, "Invocation Duration (ms)", json!([
+                ["AWS/Lambda", "Duration", "FunctionName", function_name, {"stat": "Maximum"}],
+            ])),
+        ],
+    })
+}
+
+/// Creates, or updates, a CloudWatch dashboard and alarms covering LambdUpdate's own metrics and
+/// those of its function, notifying the alarm topic if one is given.
+pub async fn setup_observability(
+    region: Option<String>,
+    function_name: &str,
+    alarm_topic_arn: Option<&str>,
+) -> Result<()> {
+    let aws_config = load_aws_config(region).await;
+    let region = aws_config
+        .region()
+        .ok_or_else(|| anyhow!("No region configured"))?
+        .to_string();
+    let client = aws_sdk_cloudwatch::Client::new(&aws_config);
+
+    debug!("Put Dashboard: {}", NAME);
+    client
+        .put_dashboard()
+        .dashboard_name(NAME)
+        .dashboard_body(dashboard_body(&region, function_name).to_string())
+        .send()
+        .await?;
+    info!("Put Dashboard Succeeded: {}", NAME);
+
+    for alarm in alarms(function_name) {
+        debug!("Put Metric Alarm: {:?}", alarm);
+        client
+            .put_metric_alarm()
+            .alarm_name(&alarm.name)
+            .namespace(alarm.namespace)
+            .metric_name(alarm.metric_name)
+            .set_dimensions(alarm.function_name.map(|function_name| {
+                vec![Dimension::builder()
+                    .name("FunctionName")
+                    .value(function_name)
+                    .build()]
+            }))
+            .statistic(alarm.statistic)
+            .period(PERIOD.num_seconds() as i32)
+            .evaluation_periods(1)
+            .threshold(alarm.threshold)
+            .comparison_operator(ComparisonOperator::GreaterThanOrEqualToThreshold)
+            .treat_missing_data("notBreaching")
+            .set_alarm_actions(alarm_topic_arn.map(|arn| vec![arn.to_string()]))
+            .send()
+            .await?;
+        info!("Put Metric Alarm Succeeded: {}", alarm.name);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_alarms() {
+        let alarms = alarms("lambdupdate");
+
+        assert_eq!(
+            vec![
+                "lambdupdate-deployment-failures",
+                "lambdupdate-deployment-duration",
+                "lambdupdate-throttles"
+            ],
+            alarms.iter().map(|a| a.name.as_str()).collect::<Vec<_>>()
+        );
+        assert_eq!(225_000.0, alarms[1].threshold);
+        assert_eq!(Some("lambdupdate"), alarms[2].function_name.as_deref());
+    }
+
+    #[test]
+    fn test_dashboard_body() {
+        let body = dashboard_body("us-east-1", "lambdupdate");
+
+        let widgets = body["widgets"].as_array().expect("widgets is an array");
+        assert_eq!(4, widgets.len());
+        for widget in widgets {
+            assert_eq!("us-east-1", widget["properties"]["region"]);
+        }
+        assert_eq!(
+            json!(["AWS/Lambda", "Throttles", "FunctionName", "lambdupdate", {"stat": "Sum"}]),
+            widgets[2]["properties"]["metrics"][2]
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use crate::{
+    approve, bundle, function_exists, get_function_names, get_object_md, get_region, update,
+    Clients, Config, Event, Record, Request, FUNCTION_NAME_MD_KEY, TIER_SEPARATOR,
+};
+use anyhow::{anyhow, Result};
+use chrono::Duration;
+use log::{debug, info, warn};
+use std::future::Future;
+
+const MAX_ATTEMPTS: u32 = 3;
+
+const INITIAL_BACKOFF: Duration = Duration::seconds(1);
+
+fn backoff(attempt: u32) -> Duration {
+    INITIAL_BACKOFF * 2_i32.pow(attempt - 1)
+}
+
+async fn with_backoff<F, Fut>(mut f: F) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let mut attempt = 1;
+
+    loop {
+        match f().await {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < MAX_ATTEMPTS => {
+                let backoff = backoff(attempt);
+                warn!(
+                    "Retry Attempt {} Failed, Retrying in {}s: {:?}",
+                    attempt,
+                    backoff.num_seconds(),
+                    e
+                );
+                tokio::time::sleep(backoff.to_std()?).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(anyhow!("Failed after {} attempts: {:?}", MAX_ATTEMPTS, e)),
+        }
+    }
+}
+
+async fn artifact_exists(clients: &Clients, record: &Record) -> Result<bool> {
+    let bucket = &record.s3.bucket.name;
+    let key = &record.s3.object.key;
+
+    debug!("Head Object: {}:{}", bucket, key);
+    match clients
+        .s3
+        .head_object()
+        .bucket(bucket)
+        .key(key)
+        .send()
+        .await
+    {
+        Ok(_) => Ok(true),
+        Err(e) if e.as_service_error().is_some_and(|e| e.is_not_found()) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Checks that a failed record's artifact, and the functions it maps to, are valid and still exist.
+/// Bundles' functions are checked when their manifest is read during the update itself.
+async fn is_retryable(clients: &Clients, config: &Config, record: &Record) -> Result<bool> {
+    let bucket = &record.s3.bucket.name;
+    let key = &record.s3.object.key;
+
+    if !artifact_exists(clients, record).await? {
+        warn!(
+            "Artifact No Longer Exists, Not Retrying: {}:{}",
+            bucket, key
+        );
+        return Ok(false);
+    }
+
+    let object_md = get_object_md(&clients.s3, record).await;
+    if object_md.contains_key(bundle::MANIFEST_MD_KEY) {
+        return Ok(true);
+    }
+
+    // Invalid metadata fails the same way every time, so isn't worth retrying.
+    let function_names =
+        match get_function_names(object_md.get(FUNCTION_NAME_MD_KEY), record, &config.groups) {
+            Ok(function_names) => function_names,
+            Err(e) => {
+                warn!(
+                    "Invalid Function Names, Not Retrying: {}:{}: {:?}",
+                    bucket, key, e
+                );
+                return Ok(false);
+            }
+        };
+
+    for function_name in function_names.split([',', TIER_SEPARATOR]) {
+        if !function_exists(&clients.lambda, function_name).await? {
+            warn!(
+                "Function No Longer Exists, Not Retrying: {} <-- {}:{}",
+                function_name, bucket, key
+            );
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// Re-attempts a request which failed and was sent to LambdUpdate's dead-letter queue, with
+/// backoff. Updates are only retried for records whose artifact and functions still exist, and
+/// rollout requests aren't retried at all, since the next scheduled one supersedes them.
+pub async fn retry(request: Request, config: &Config) -> Result<()> {
+    debug!("Retry: {:?}", request);
+
+    match request {
+        Request::Approval { approval } => {
+            with_backoff(|| approve(approval.clone(), config)).await?;
+        }
+        Request::Rollout { .. } => info!("Rollout Requests Aren't Retried"),
+        Request::Update(event) => {
+            let clients = Clients::new(get_region(&event.records)?).await;
+
+            let mut records = Vec::with_capacity(event.records.len());
+            for record in event.records {
+                if is_retryable(&clients, config, &record).await? {
+                    records.push(record);
+                }
+            }
+
+            if records.is_empty() {
+                return Ok(());
+            }
+
+            let event = Event { records };
+            with_backoff(|| update(event.clone(), config)).await?;
+        }
+    }
+
+    info!("Retry Succeeded");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(
+            vec![1, 2, 4],
+            (1..=MAX_ATTEMPTS)
+                .map(|a| backoff(a).num_seconds())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_with_backoff() -> Result<()> {
+        let attempts = AtomicU32::new(0);
+
+        with_backoff(|| async {
+            match attempts.fetch_add(1, Ordering::SeqCst) {
+                0 => Err(anyhow!("throttled")),
+                _ => Ok(()),
+            }
+        })
+        .await?;
+        assert_eq!(2, attempts.load(Ordering::SeqCst));
+
+        let attempts = AtomicU32::new(0);
+        let res = with_backoff(|| async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(anyhow!("throttled"))
+        })
+        .await;
+        assert!(res.is_err());
+        assert_eq!(MAX_ATTEMPTS, attempts.load(Ordering::SeqCst));
+
+        Ok(())
+    }
+}
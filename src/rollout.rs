@@ -0,0 +1,781 @@
+use crate::{shutdown, Clients, Config};
+use anyhow::{anyhow, Result};
+use aws_sdk_cloudwatch::primitives::DateTime;
+use aws_sdk_cloudwatch::types::{Dimension, StateValue, Statistic};
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_lambda::client::Waiters;
+use aws_sdk_lambda::types::AliasRoutingConfiguration;
+use chrono::{Duration, Utc};
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+pub(crate) const ALIAS_ENV_VAR: &str = "LAMBDUPDATE_ROLLOUT_ALIAS";
+
+pub(crate) const TABLE_ENV_VAR: &str = "LAMBDUPDATE_ROLLOUT_TABLE";
+
+pub(crate) const STEPS_ENV_VAR: &str = "LAMBDUPDATE_ROLLOUT_STEPS";
+
+pub(crate) const INTERVAL_SECS_ENV_VAR: &str = "LAMBDUPDATE_ROLLOUT_INTERVAL_SECS";
+
+pub(crate) const MAX_ERRORS_ENV_VAR: &str = "LAMBDUPDATE_ROLLOUT_MAX_ERRORS";
+
+pub(crate) const ALARMS_ENV_VAR: &str = "LAMBDUPDATE_ROLLBACK_ALARMS";
+
+pub(crate) const BAKE_SECS_ENV_VAR: &str = "LAMBDUPDATE_BAKE_SECS";
+
+pub(crate) const DEFAULT_STEPS: [u8; 3] = [10, 50, 100];
+
+pub(crate) const DEFAULT_INTERVAL: Duration = Duration::minutes(10);
+
+/// How long to wait for a code update to finish before publishing a version.
+pub(crate) const UPDATE_WAIT: Duration = Duration::seconds(45);
+
+/// CloudWatch returns at most this many datapoints per request.
+const MAX_DATAPOINTS: i64 = 1440;
+
+const FUNCTION_NAME_ATTR: &str = "function_name";
+
+const ALIAS_ATTR: &str = "alias";
+
+const STABLE_VERSION_ATTR: &str = "stable_version";
+
+const NEW_VERSION_ATTR: &str = "new_version";
+
+const STEP_ATTR: &str = "step";
+
+const STATUS_ATTR: &str = "status";
+
+const STEP_STARTED_AT_ATTR: &str = "step_started_at";
+
+const NEXT_STEP_AT_ATTR: &str = "next_step_at";
+
+/// Parses a comma-separated list of increasing traffic percentages, e.g. `10,50,100`. A final
+/// `100` is implied if missing.
+pub(crate) fn parse_steps(steps: &str) -> Result<Vec<u8>> {
+    let mut parsed = Vec::new();
+
+    for step in crate::split_list(steps) {
+        let step: u8 = step
+            .parse()
+            .map_err(|_| anyhow!("Invalid rollout step: {}", step))?;
+
+        if step == 0 || step > 100 {
+            return Err(anyhow!("Rollout steps must be between 1 and 100: {}", step));
+        }
+
+        if parsed.last().is_some_and(|last| *last >= step) {
+            return Err(anyhow!("Rollout steps must be increasing: {}", steps));
+        }
+
+        parsed.push(step);
+    }
+
+    if parsed.last() != Some(&100) {
+        parsed.push(100);
+    }
+
+    Ok(parsed)
+}
+
+/// A request to advance in-progress rollouts, e.g. `{"rollout": {}}` from a scheduled rule.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RolloutRequest {}
+
+/// A rollout's progress, for display.
+#[derive(Debug)]
+pub(crate) struct RolloutProgress {
+    pub(crate) function_name: String,
+    pub(crate) alias: String,
+    pub(crate) new_version: String,
+    pub(crate) status: &'static str,
+    /// The percentage of the alias's traffic routed to the new version.
+    pub(crate) weight: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RolloutStatus {
+    InProgress,
+    /// Fully shifted to the new version, but still watched for failures until the bake period ends.
+    Baking,
+    Completed,
+    RolledBack,
+}
+
+impl RolloutStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::InProgress => "in-progress",
+            Self::Baking => "baking",
+            Self::Completed => "completed",
+            Self::RolledBack => "rolled-back",
+        }
+    }
+
+    fn parse(status: &str) -> Result<Self> {
+        match status {
+            "in-progress" => Ok(Self::InProgress),
+            "baking" => Ok(Self::Baking),
+            "completed" => Ok(Self::Completed),
+            "rolled-back" => Ok(Self::RolledBack),
+            _ => Err(anyhow!("Invalid rollout status: {}", status)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct RolloutState {
+    function_name: String,
+    alias: String,
+    stable_version: String,
+    new_version: String,
+    step: usize,
+    status: RolloutStatus,
+    step_started_at: i64,
+    next_step_at: i64,
+}
+
+fn get_s(item: &HashMap<String, AttributeValue>, name: &str) -> Result<String> {
+    item.get(name)
+        .and_then(|v| v.as_s().ok())
+        .cloned()
+        .ok_or_else(|| anyhow!("Missing rollout attribute: {}", name))
+}
+
+fn get_n(item: &HashMap<String, AttributeValue>, name: &str) -> Result<i64> {
+    item.get(name)
+        .and_then(|v| v.as_n().ok())
+        .ok_or_else(|| anyhow!("Missing rollout attribute: {}", name))?
+        .parse()
+        .map_err(|_| anyhow!("Invalid rollout attribute: {}", name))
+}
+
+impl RolloutState {
+    fn to_item(&self) -> HashMap<String, AttributeValue> {
+        HashMap::from([
+            (
+                FUNCTION_NAME_ATTR.to_string(),
+                AttributeValue::S(self.function_name.clone()),
+            ),
+            (
+                ALIAS_ATTR.to_string(),
+                AttributeValue::S(self.alias.clone()),
+            ),
+            (
+                STABLE_VERSION_ATTR.to_string(),
+                AttributeValue::S(self.stable_version.clone()),
+            ),
+            (
+                NEW_VERSION_ATTR.to_string(),
+                AttributeValue::S(self.new_version.clone()),
+            ),
+            (
+                STEP_ATTR.to_string(),
+                AttributeValue::N(self.step.to_string()),
+            ),
+            (
+                STATUS_ATTR.to_string(),
+                AttributeValue::S(self.status.as_str().to_string()),
+            ),
+            (
+                STEP_STARTED_AT_ATTR.to_string(),
+                AttributeValue::N(self.step_started_at.to_string()),
+            ),
+            (
+                NEXT_STEP_AT_ATTR.to_string(),
+                AttributeValue::N(self.next_step_at.to_string()),
+            ),
+        ])
+    }
+
+    fn from_item(item: &HashMap<String, AttributeValue>) -> Result<Self> {
+        Ok(Self {
+            function_name: get_s(item, FUNCTION_NAME_ATTR)?,
+            alias: get_s(item, ALIAS_ATTR)?,
+            stable_version: get_s(item, STABLE_VERSION_ATTR)?,
+            new_version: get_s(item, NEW_VERSION_ATTR)?,
+            step: get_n(item, STEP_ATTR)? as usize,
+            status: RolloutStatus::parse(&get_s(item, STATUS_ATTR)?)?,
+            step_started_at: get_n(item, STEP_STARTED_AT_ATTR)?,
+            next_step_at: get_n(item, NEXT_STEP_AT_ATTR)?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    Wait,
+    RollBack,
+    Shift(u8),
+    Promote,
+    Complete,
+}
+
+fn next_action(state: &RolloutState, steps: &[u8], failing: bool, now: i64) -> Action {
+    if failing {
+        Action::RollBack
+    } else if now < state.next_step_at {
+        Action::Wait
+    } else if state.status == RolloutStatus::Baking {
+        Action::Complete
+    } else {
+        match steps.get(state.step + 1) {
+            Some(100) | None => Action::Promote,
+            Some(weight) => Action::Shift(*weight),
+        }
+    }
+}
+
+/// Picks the finest metric period, in seconds, which covers a window in a single request.
+fn metric_period(window_secs: i64) -> i32 {
+    let period = (window_secs + MAX_DATAPOINTS - 1) / MAX_DATAPOINTS;
+    let period = ((period + 59) / 60).max(1) * 60;
+    period.try_into().unwrap_or(i32::MAX)
+}
+
+fn routing(version: &str, weight: u8) -> AliasRoutingConfiguration {
+    AliasRoutingConfiguration::builder()
+        .additional_version_weights(version, f64::from(weight) / 100.0)
+        .build()
+}
+
+fn no_routing() -> AliasRoutingConfiguration {
+    AliasRoutingConfiguration::builder()
+        .set_additional_version_weights(Some(HashMap::new()))
+        .build()
+}
+
+/// Shifts an alias's traffic to newly published versions in steps, with state held in a DynamoDB
+/// table keyed by function name so that subsequent invocations can continue each rollout.
+#[derive(Debug, Clone)]
+pub(crate) struct Rollouts {
+    lambda: aws_sdk_lambda::Client,
+    dynamodb: aws_sdk_dynamodb::Client,
+    cloudwatch: aws_sdk_cloudwatch::Client,
+    table: String,
+    alias: String,
+    steps: Vec<u8>,
+    interval: Duration,
+    max_errors: f64,
+    alarms: Vec<String>,
+    bake_period: Duration,
+}
+
+impl Rollouts {
+    pub(crate) fn new(clients: &Clients, config: &Config) -> Option<Self> {
+        match (&config.rollout_alias, &config.rollout_table) {
+            (Some(alias), Some(table)) => Some(Self {
+                lambda: clients.lambda.clone(),
+                dynamodb: clients.dynamodb.clone(),
+                cloudwatch: clients.cloudwatch.clone(),
+                table: table.clone(),
+                alias: alias.clone(),
+                steps: config.rollout_steps.clone(),
+                interval: config.rollout_interval,
+                max_errors: config.rollout_max_errors,
+                alarms: config.rollback_alarms.clone(),
+                bake_period: config.bake_period,
+            }),
+            _ => None,
+        }
+    }
+
+    async fn publish_version(
+        &self,
+        function_name: &str,
+        code_sha256: Option<&str>,
+    ) -> Result<String> {
+        debug!("Wait For Function Update: {}", function_name);
+        self.lambda
+            .wait_until_function_updated()
+            .function_name(function_name)
+            .wait(UPDATE_WAIT.to_std()?)
+            .await?;
+
+        debug!("Publish Version: {}", function_name);
+        let output = self
+            .lambda
+            .publish_version()
+            .function_name(function_name)
+            .set_code_sha256(code_sha256.map(String::from))
+            .send()
+            .await?;
+
+        let version = output
+            .version
+            .ok_or_else(|| anyhow!("No version published for {}", function_name))?;
+
+        info!("Publish Version Succeeded: {}:{}", function_name, version);
+
+        Ok(version)
+    }
+
+    async fn update_alias(
+        &self,
+        function_name: &str,
+        version: &str,
+        routing: AliasRoutingConfiguration,
+    ) -> Result<()> {
+        debug!(
+            "Update Alias: {}:{} --> {} {:?}",
+            function_name, self.alias, version, routing
+        );
+
+        self.lambda
+            .update_alias()
+            .function_name(function_name)
+            .name(&self.alias)
+            .function_version(version)
+            .routing_config(routing)
+            .send()
+            .await?;
+
+        info!(
+            "Update Alias Succeeded: {}:{} --> {}",
+            function_name, self.alias, version
+        );
+
+        Ok(())
+    }
+
+    async fn put_state(&self, state: &RolloutState) -> Result<()> {
+        debug!("Put Rollout State: {:?}", state);
+
+        self.dynamodb
+            .put_item()
+            .table_name(&self.table)
+            .set_item(Some(state.to_item()))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_state(&self, function_name: &str) -> Result<Option<RolloutState>> {
+        debug!("Get Rollout State: {}", function_name);
+
+        let output = self
+            .dynamodb
+            .get_item()
+            .table_name(&self.table)
+            .key(
+                FUNCTION_NAME_ATTR,
+                AttributeValue::S(function_name.to_string()),
+            )
+            .consistent_read(true)
+            .send()
+            .await?;
+
+        output.item().map(RolloutState::from_item).transpose()
+    }
+
+    /// Rolls the alias back from a version published by [Rollouts::start], e.g. if it failed a
+    /// health check.
+    pub(crate) async fn roll_back(&self, function_name: &str, version: &str) -> Result<()> {
+        let mut state = match self.get_state(function_name).await? {
+            Some(state)
+                if state.new_version == version && state.status != RolloutStatus::RolledBack =>
+            {
+                state
+            }
+            _ => {
+                warn!("No Rollout To Roll Back: {}:{}", function_name, version);
+                return Ok(());
+            }
+        };
+
+        self.update_alias(&state.function_name, &state.stable_version, no_routing())
+            .await?;
+        state.status = RolloutStatus::RolledBack;
+        self.put_state(&state).await?;
+
+        error!(
+            "Rollout Rolled Back: {}:{} --> {}",
+            state.function_name, state.alias, state.stable_version
+        );
+
+        Ok(())
+    }
+
+    /// Shifts all of the alias's traffic to the new version, then bakes it if a bake period is set.
+    async fn promote(&self, state: &mut RolloutState, now: i64) -> Result<()> {
+        self.update_alias(&state.function_name, &state.new_version, no_routing())
+            .await?;
+
+        if self.bake_period > Duration::zero() {
+            state.status = RolloutStatus::Baking;
+            state.step_started_at = now;
+            state.next_step_at = now + self.bake_period.num_seconds();
+            info!(
+                "Rollout Baking: {}:{} --> {} until {}",
+                state.function_name, state.alias, state.new_version, state.next_step_at
+            );
+        } else {
+            state.status = RolloutStatus::Completed;
+            info!(
+                "Rollout Completed: {}:{} --> {}",
+                state.function_name, state.alias, state.new_version
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Publishes a version of a just-updated function and shifts the first step of the alias's
+    /// traffic to it, returning the published version.
+    pub(crate) async fn start(
+        &self,
+        function_name: &str,
+        code_sha256: Option<&str>,
+    ) -> Result<String> {
+        let new_version = self.publish_version(function_name, code_sha256).await?;
+
+        debug!("Get Alias: {}:{}", function_name, self.alias);
+        let stable_version = self
+            .lambda
+            .get_alias()
+            .function_name(function_name)
+            .name(&self.alias)
+            .send()
+            .await?
+            .function_version
+            .ok_or_else(|| anyhow!("No version for alias {}:{}", function_name, self.alias))?;
+
+        if stable_version == new_version {
+            info!(
+                "Alias Already Points To Version: {}:{} --> {}",
+                function_name, self.alias, new_version
+            );
+            return Ok(new_version);
+        }
+
+        let now = Utc::now().timestamp();
+
+        let mut state = RolloutState {
+            function_name: function_name.to_string(),
+            alias: self.alias.clone(),
+            stable_version,
+            new_version: new_version.clone(),
+            step: 0,
+            status: RolloutStatus::InProgress,
+            step_started_at: now,
+            next_step_at: now + self.interval.num_seconds(),
+        };
+
+        match self.steps.first() {
+            Some(100) | None => self.promote(&mut state, now).await?,
+            Some(weight) => {
+                self.update_alias(
+                    function_name,
+                    &state.stable_version,
+                    routing(&new_version, *weight),
+                )
+                .await?;
+                info!(
+                    "Rollout Started: {}:{} --> {} at {}%",
+                    function_name, self.alias, new_version, weight
+                );
+            }
+        }
+
+        self.put_state(&state).await?;
+
+        Ok(new_version)
+    }
+
+    async fn get_errors(&self, state: &RolloutState, now: i64) -> Result<f64> {
+        let window = (now - state.step_started_at).max(1);
+
+        debug!(
+            "Get Metric Statistics: Errors {}:{} ({}) over {}s",
+            state.function_name, state.alias, state.new_version, window
+        );
+
+        let mut dimensions = vec![
+            Dimension::builder()
+                .name("FunctionName")
+                .value(&state.function_name)
+                .build(),
+            Dimension::builder()
+                .name("Resource")
+                .value(format!("{}:{}", state.function_name, state.alias))
+                .build(),
+        ];
+
+        // Lambda only reports errors by executed version while an alias splits traffic.
+        if state.status == RolloutStatus::InProgress {
+            dimensions.push(
+                Dimension::builder()
+                    .name("ExecutedVersion")
+                    .value(&state.new_version)
+                    .build(),
+            );
+        }
+
+        let output = self
+            .cloudwatch
+            .get_metric_statistics()
+            .namespace("AWS/Lambda")
+            .metric_name("Errors")
+            .set_dimensions(Some(dimensions))
+            .start_time(DateTime::from_secs(state.step_started_at))
+            .end_time(DateTime::from_secs(now))
+            .period(metric_period(window))
+            .statistics(Statistic::Sum)
+            .send()
+            .await?;
+
+        Ok(output.datapoints().iter().filter_map(|d| d.sum()).sum())
+    }
+
+    async fn get_firing_alarms(&self) -> Result<Vec<String>> {
+        if self.alarms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        debug!("Describe Alarms: {:?}", self.alarms);
+        let output = self
+            .cloudwatch
+            .describe_alarms()
+            .set_alarm_names(Some(self.alarms.clone()))
+            .state_value(StateValue::Alarm)
+            .send()
+            .await?;
+
+        Ok(output
+            .metric_alarms()
+            .iter()
+            .filter_map(|a| a.alarm_name())
+            .chain(
+                output
+                    .composite_alarms()
+                    .iter()
+                    .filter_map(|a| a.alarm_name()),
+            )
+            .map(String::from)
+            .collect())
+    }
+
+    /// Advances a rollout, returning its new status.
+    async fn advance(&self, mut state: RolloutState, now: i64) -> Result<RolloutStatus> {
+        let errors = self.get_errors(&state, now).await?;
+        let firing_alarms = self.get_firing_alarms().await?;
+
+        let failing = errors > self.max_errors || !firing_alarms.is_empty();
+
+        match next_action(&state, &self.steps, failing, now) {
+            Action::Wait => {
+                debug!(
+                    "Rollout Waiting: {}:{} --> {} until {}",
+                    state.function_name, state.alias, state.new_version, state.next_step_at
+                );
+                return Ok(state.status);
+            }
+            Action::RollBack => {
+                self.update_alias(&state.function_name, &state.stable_version, no_routing())
+                    .await?;
+                state.status = RolloutStatus::RolledBack;
+                error!(
+                    "Rollout Rolled Back: {}:{} --> {} after {} error(s), alarms: {:?}",
+                    state.function_name, state.alias, state.stable_version, errors, firing_alarms
+                );
+            }
+            Action::Shift(weight) => {
+                self.update_alias(
+                    &state.function_name,
+                    &state.stable_version,
+                    routing(&state.new_version, weight),
+                )
+                .await?;
+                state.step += 1;
+                state.step_started_at = now;
+                state.next_step_at = now + self.interval.num_seconds();
+                info!(
+                    "Rollout Advanced: {}:{} --> {} at {}%",
+                    state.function_name, state.alias, state.new_version, weight
+                );
+            }
+            Action::Promote => self.promote(&mut state, now).await?,
+            Action::Complete => {
+                state.status = RolloutStatus::Completed;
+                info!(
+                    "Rollout Completed: {}:{} --> {}",
+                    state.function_name, state.alias, state.new_version
+                );
+            }
+        }
+
+        self.put_state(&state).await?;
+
+        Ok(state.status)
+    }
+
+    async fn in_progress(&self) -> Result<Vec<RolloutState>> {
+        debug!("Scan Rollout State: {}", self.table);
+
+        let items = self
+            .dynamodb
+            .scan()
+            .table_name(&self.table)
+            .filter_expression("#s IN (:in_progress, :baking)")
+            .expression_attribute_names("#s", STATUS_ATTR)
+            .expression_attribute_values(
+                ":in_progress",
+                AttributeValue::S(RolloutStatus::InProgress.as_str().to_string()),
+            )
+            .expression_attribute_values(
+                ":baking",
+                AttributeValue::S(RolloutStatus::Baking.as_str().to_string()),
+            )
+            .into_paginator()
+            .items()
+            .send()
+            .collect::<Result<Vec<_>, _>>()
+            .await?;
+
+        items.iter().map(RolloutState::from_item).collect()
+    }
+
+    /// Lists in-progress and baking rollouts for display.
+    pub(crate) async fn progress(&self) -> Result<Vec<RolloutProgress>> {
+        Ok(self
+            .in_progress()
+            .await?
+            .into_iter()
+            .map(|state| RolloutProgress {
+                weight: match state.status {
+                    RolloutStatus::InProgress => self.steps.get(state.step).copied().unwrap_or(100),
+                    _ => 100,
+                },
+                status: state.status.as_str(),
+                function_name: state.function_name,
+                alias: state.alias,
+                new_version: state.new_version,
+            })
+            .collect())
+    }
+
+    /// Advances every in-progress or baking rollout, failing if any were rolled back.
+    pub(crate) async fn advance_all(&self) -> Result<()> {
+        let now = Utc::now().timestamp();
+
+        let mut rolled_back = 0;
+
+        for state in self.in_progress().await? {
+            if shutdown::requested() {
+                warn!("Shutdown Requested, Skipping Remaining Rollouts");
+                break;
+            }
+
+            let function_name = state.function_name.clone();
+            match self.advance(state, now).await {
+                Ok(RolloutStatus::RolledBack) => rolled_back += 1,
+                Ok(_) => (),
+                Err(e) => warn!("Rollout Advance Failed: {}: {:?}", function_name, e),
+            }
+        }
+
+        if rolled_back > 0 {
+            Err(anyhow!("{} rollout(s) rolled back", rolled_back))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn state() -> RolloutState {
+        RolloutState {
+            function_name: "foo".to_string(),
+            alias: "live".to_string(),
+            stable_version: "1".to_string(),
+            new_version: "2".to_string(),
+            step: 0,
+            status: RolloutStatus::InProgress,
+            step_started_at: 0,
+            next_step_at: 600,
+        }
+    }
+
+    #[test]
+    fn test_parse_steps() -> Result<()> {
+        assert_eq!(vec![10, 50, 100], parse_steps("10,50,100")?);
+        assert_eq!(vec![10, 50, 100], parse_steps("10, 50")?);
+        assert_eq!(vec![100], parse_steps("100")?);
+        assert_eq!(vec![100], parse_steps("")?);
+
+        assert!(parse_steps("0,50").is_err());
+        assert!(parse_steps("10,101").is_err());
+        assert!(parse_steps("50,10").is_err());
+        assert!(parse_steps("10,10").is_err());
+        assert!(parse_steps("ten").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_state_item_round_trip() -> Result<()> {
+        let state = state();
+
+        assert_eq!(state, RolloutState::from_item(&state.to_item())?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_state_from_item_missing_attribute() {
+        let mut item = state().to_item();
+        item.remove(NEW_VERSION_ATTR);
+
+        let res = RolloutState::from_item(&item);
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert!(e.to_string().contains(NEW_VERSION_ATTR));
+        }
+    }
+
+    #[test]
+    fn test_next_action() {
+        let steps = [10, 50, 100];
+        let mut state = state();
+
+        assert_eq!(Action::Wait, next_action(&state, &steps, false, 599));
+        assert_eq!(Action::Shift(50), next_action(&state, &steps, false, 600));
+
+        state.step = 1;
+        assert_eq!(Action::Promote, next_action(&state, &steps, false, 600));
+    }
+
+    #[test]
+    fn test_next_action_baking() {
+        let steps = [10, 50, 100];
+        let mut state = state();
+        state.step = 2;
+        state.status = RolloutStatus::Baking;
+
+        assert_eq!(Action::Wait, next_action(&state, &steps, false, 599));
+        assert_eq!(Action::Complete, next_action(&state, &steps, false, 600));
+        assert_eq!(Action::RollBack, next_action(&state, &steps, true, 599));
+    }
+
+    #[test]
+    fn test_next_action_rollback() {
+        let steps = [10, 50, 100];
+        let state = state();
+
+        assert_eq!(Action::RollBack, next_action(&state, &steps, true, 0));
+        assert_eq!(Action::RollBack, next_action(&state, &steps, true, 600));
+    }
+
+    #[test]
+    fn test_metric_period() {
+        assert_eq!(60, metric_period(1));
+        assert_eq!(60, metric_period(600));
+        assert_eq!(60, metric_period(86_400));
+        assert_eq!(120, metric_period(86_401));
+        assert_eq!(420, metric_period(7 * 86_400));
+    }
+}
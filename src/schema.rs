@@ -0,0 +1,211 @@
+use crate::{
+    bundle, config_file, function_name, health, FUNCTION_NAME_MD_KEY, ORDER_MD_KEY, TIER_SEPARATOR,
+};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+/// Object metadata key opting an object into validation of all of its metadata.
+pub(crate) const SCHEMA_MD_KEY: &str = "lambdupdate.schema";
+
+const SCHEMA_VERSION: &str = "2";
+
+/// How many edits apart a key may be from a directive to be suggested as a misspelling of it.
+const MAX_SUGGESTION_DISTANCE: usize = 3;
+
+/// Every metadata key LambdUpdate recognizes.
+const DIRECTIVES: [&str; 5] = [
+    SCHEMA_MD_KEY,
+    FUNCTION_NAME_MD_KEY,
+    bundle::MANIFEST_MD_KEY,
+    health::URL_MD_KEY,
+    ORDER_MD_KEY,
+];
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut distances = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut previous = distances[0];
+        distances[0] = i + 1;
+
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous + usize::from(a != *b);
+            previous = distances[j + 1];
+            distances[j + 1] = substitution.min(previous + 1).min(distances[j] + 1);
+        }
+    }
+
+    distances[b.len()]
+}
+
+fn suggestion(key: &str) -> Option<&'static str> {
+    DIRECTIVES
+        .into_iter()
+        .map(|d| (edit_distance(key, d), d))
+        .filter(|(distance, _)| *distance <= MAX_SUGGESTION_DISTANCE)
+        .min()
+        .map(|(_, d)| d)
+}
+
+fn validate_value(key: &str, value: &str) -> Result<()> {
+    match key {
+        SCHEMA_MD_KEY => Ok(()),
+        FUNCTION_NAME_MD_KEY => value
+            .split([',', TIER_SEPARATOR])
+            .try_for_each(|function_name| {
+                match function_name.strip_prefix(config_file::GROUP_PREFIX) {
+                    Some("") => Err(anyhow!("group name is empty")),
+                    Some(_) => Ok(()),
+                    None => function_name::validate(function_name)
+                        .map_err(|e| anyhow!("invalid function name {:?}: {}", function_name, e)),
+                }
+            }),
+        bundle::MANIFEST_MD_KEY if value.trim().is_empty() => Err(anyhow!("path is empty")),
+        bundle::MANIFEST_MD_KEY => Ok(()),
+        health::URL_MD_KEY => match reqwest::Url::parse(value) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(()),
+            Ok(url) => Err(anyhow!("unsupported scheme {:?}", url.scheme())),
+            Err(e) => Err(anyhow!("invalid URL: {}", e)),
+        },
+        ORDER_MD_KEY => value
+            .trim()
+            .parse::<i64>()
+            .map(|_| ())
+            .map_err(|_| anyhow!("not an integer")),
+        _ => Err(match suggestion(key) {
+            Some(directive) => anyhow!("unknown directive, did you mean '{}'?", directive),
+            None => anyhow!("unknown directive"),
+        }),
+    }
+}
+
+/// Validates an object's metadata if it opts in with `lambdupdate.schema=2`, rejecting unknown
+/// keys and malformed values. Objects without a schema are left unvalidated.
+pub(crate) fn validate(object_md: &HashMap<String, String>) -> Result<()> {
+    match object_md.get(SCHEMA_MD_KEY).map(|s| s.trim()) {
+        None => return Ok(()),
+        Some(SCHEMA_VERSION) => (),
+        Some(version) => {
+            return Err(anyhow!(
+                "Unsupported '{}': {:?}, expected {}",
+                SCHEMA_MD_KEY,
+                version,
+                SCHEMA_VERSION
+            ))
+        }
+    }
+
+    let mut keys = object_md.keys().collect::<Vec<_>>();
+    keys.sort();
+
+    let mut errors = keys
+        .into_iter()
+        .filter_map(|key| {
+            validate_value(key, &object_md[key])
+                .err()
+                .map(|e| format!("'{}': {}", key, e))
+        })
+        .collect::<Vec<_>>();
+
+    if object_md.contains_key(FUNCTION_NAME_MD_KEY)
+        && object_md.contains_key(bundle::MANIFEST_MD_KEY)
+    {
+        errors.push(format!(
+            "'{}' and '{}' are mutually exclusive",
+            FUNCTION_NAME_MD_KEY,
+            bundle::MANIFEST_MD_KEY
+        ));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("{}", errors.join("; ")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn object_md(md: &[(&str, &str)]) -> HashMap<String, String> {
+        md.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_unversioned() -> Result<()> {
+        validate(&object_md(&[("function.name", "foo bar")]))
+    }
+
+    #[test]
+    fn test_validate() -> Result<()> {
+        validate(&object_md(&[
+            ("lambdupdate.schema", "2"),
+            ("function.names", "foo,bar,group:checkout"),
+            ("health.url", "https://example.com/health"),
+            ("deploy.order", "1"),
+        ]))?;
+
+        validate(&object_md(&[
+            ("lambdupdate.schema", "2"),
+            ("bundle.manifest", "manifest.json"),
+        ]))
+    }
+
+    #[test]
+    fn test_validate_unsupported_version() {
+        let res = validate(&object_md(&[("lambdupdate.schema", "3")]));
+
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert!(e.to_string().contains("Unsupported 'lambdupdate.schema'"));
+        }
+    }
+
+    #[test]
+    fn test_validate_invalid() {
+        let res = validate(&object_md(&[
+            ("lambdupdate.schema", "2"),
+            ("function.name", "foo"),
+            ("owner", "payments"),
+            ("health.url", "ftp://example.com"),
+            ("deploy.order", "first"),
+        ]));
+
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert_eq!(
+                "'deploy.order': not an integer; \
+                 'function.name': unknown directive, did you mean 'function.names'?; \
+                 'health.url': unsupported scheme \"ftp\"; \
+                 'owner': unknown directive",
+                e.to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_mutually_exclusive() {
+        let res = validate(&object_md(&[
+            ("lambdupdate.schema", "2"),
+            ("function.names", "foo"),
+            ("bundle.manifest", "manifest.json"),
+        ]));
+
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert!(e.to_string().contains("mutually exclusive"));
+        }
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(0, edit_distance("deploy.order", "deploy.order"));
+        assert_eq!(1, edit_distance("deploy.ordr", "deploy.order"));
+        assert_eq!(2, edit_distance("helth.uri", "health.url"));
+        assert_eq!(3, edit_distance("", "abc"));
+    }
+}
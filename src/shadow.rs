@@ -0,0 +1,168 @@
+use crate::{function_exists, health, update_function_code, Config, Target};
+use anyhow::{anyhow, Result};
+use aws_sdk_lambda::primitives::Blob;
+use chrono::Duration;
+use log::{debug, info};
+
+pub(crate) const SUFFIX_ENV_VAR: &str = "LAMBDUPDATE_SHADOW_SUFFIX";
+
+pub(crate) const PAYLOAD_ENV_VAR: &str = "LAMBDUPDATE_SHADOW_PAYLOAD";
+
+pub(crate) const DEFAULT_PAYLOAD: &str = "{}";
+
+/// The longest part of a failed smoke test's response to include in its error.
+const MAX_ERROR_LEN: usize = 500;
+
+/// Shadow, or canary, copies of functions which are updated and smoke tested before the functions
+/// themselves, validating new code on real infrastructure without touching production traffic.
+#[derive(Debug, Clone)]
+pub(crate) struct Shadows {
+    suffix: String,
+    payload: String,
+    timeout: Duration,
+}
+
+impl Shadows {
+    pub(crate) fn new(config: &Config) -> Option<Self> {
+        config.shadow_suffix.as_ref().map(|suffix| Self {
+            suffix: suffix.clone(),
+            payload: config.shadow_payload.clone(),
+            timeout: config.health_check_timeout,
+        })
+    }
+
+    /// Updates a target's shadow with its code and smoke tests it. Functions without a shadow are
+    /// skipped, so shadows only need to exist for the functions which warrant them.
+    pub(crate) async fn deploy(
+        &self,
+        lambda_client: &aws_sdk_lambda::Client,
+        target: &Target,
+        bucket: &str,
+        key: &str,
+    ) -> Result<()> {
+        let shadow_name = shadow_name(&target.function_name, &self.suffix);
+
+        if !function_exists(lambda_client, &shadow_name).await? {
+            debug!("No Shadow Function, Skipping: {}", shadow_name);
+            return Ok(());
+        }
+
+        update_function_code(lambda_client, &shadow_name, &target.code, bucket, key).await?;
+        health::wait_for_updates(lambda_client, &[&shadow_name], self.timeout).await?;
+
+        smoke_test(lambda_client, &shadow_name, &self.payload, self.timeout).await
+    }
+}
+
+/// Names a function's shadow by appending the suffix to its name, keeping any ARN prefix or
+/// qualifier, e.g. `orders-api:prod` --> `orders-api-canary:prod`.
+fn shadow_name(function_name: &str, suffix: &str) -> String {
+    let mut parts = function_name
+        .split(':')
+        .map(String::from)
+        .collect::<Vec<_>>();
+
+    let name_index = match parts.as_slice() {
+        [arn, ..] if arn == "arn" && parts.len() > 6 => 6,
+        [_, function, _, ..] if function == "function" => 2,
+        _ => 0,
+    };
+    parts[name_index].push_str(suffix);
+
+    parts.join(":")
+}
+
+fn check_response(
+    function_name: &str,
+    function_error: Option<&str>,
+    payload: Option<&[u8]>,
+) -> Result<()> {
+    match function_error {
+        Some(function_error) => {
+            let payload = String::from_utf8_lossy(payload.unwrap_or_default());
+            Err(anyhow!(
+                "Smoke test of {} failed: {}: {}",
+                function_name,
+                function_error,
+                payload.chars().take(MAX_ERROR_LEN).collect::<String>()
+            ))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Invokes a shadow function with the smoke test payload, failing if the function does or doesn't
+/// respond within `timeout`.
+async fn smoke_test(
+    lambda_client: &aws_sdk_lambda::Client,
+    function_name: &str,
+    payload: &str,
+    timeout: Duration,
+) -> Result<()> {
+    debug!("Invoke: {} {}", function_name, payload);
+    let invoke = lambda_client
+        .invoke()
+        .function_name(function_name)
+        .payload(Blob::new(payload))
+        .send();
+
+    let output = tokio::time::timeout(timeout.to_std()?, invoke)
+        .await
+        .map_err(|_| {
+            anyhow!(
+                "Smoke test of {} timed out after {}s",
+                function_name,
+                timeout.num_seconds()
+            )
+        })??;
+
+    check_response(
+        function_name,
+        output.function_error(),
+        output.payload().map(Blob::as_ref),
+    )?;
+
+    info!("Smoke Test Succeeded: {}", function_name);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_shadow_name() {
+        assert_eq!("foo-canary", shadow_name("foo", "-canary"));
+        assert_eq!("foo-canary:prod", shadow_name("foo:prod", "-canary"));
+        assert_eq!(
+            "123456789012:function:foo-canary",
+            shadow_name("123456789012:function:foo", "-canary")
+        );
+        assert_eq!(
+            "arn:aws:lambda:us-east-1:123456789012:function:foo-canary:7",
+            shadow_name(
+                "arn:aws:lambda:us-east-1:123456789012:function:foo:7",
+                "-canary"
+            )
+        );
+    }
+
+    #[test]
+    fn test_check_response() {
+        assert!(check_response("foo", None, Some(b"null")).is_ok());
+
+        let res = check_response(
+            "foo",
+            Some("Unhandled"),
+            Some(br#"{"errorMessage":"boom"}"#),
+        );
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert_eq!(
+                r#"Smoke test of foo failed: Unhandled: {"errorMessage":"boom"}"#,
+                e.to_string()
+            );
+        }
+    }
+}
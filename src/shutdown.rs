@@ -0,0 +1,214 @@
+use anyhow::{anyhow, Result};
+use chrono::Duration;
+use log::{debug, info, warn};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::Notify;
+
+pub(crate) const BUDGET_SECS_ENV_VAR: &str = "LAMBDUPDATE_SHUTDOWN_BUDGET_SECS";
+
+/// Short enough to finish within the 10s grace period container runtimes give by default.
+pub(crate) const DEFAULT_BUDGET: Duration = Duration::seconds(8);
+
+/// Lambda gives a runtime as little as 500ms between SIGTERM and SIGKILL.
+pub(crate) const LAMBDA_BUDGET: Duration = Duration::milliseconds(400);
+
+/// Set by Lambda in its runtime environment.
+const LAMBDA_FUNCTION_NAME_ENV_VAR: &str = "AWS_LAMBDA_FUNCTION_NAME";
+
+/// How long in-flight work gets to finish by default: Lambda's budget when running in Lambda, and
+/// the container runtimes' otherwise.
+pub(crate) fn default_budget() -> Duration {
+    if std::env::var_os(LAMBDA_FUNCTION_NAME_ENV_VAR).is_some() {
+        LAMBDA_BUDGET
+    } else {
+        DEFAULT_BUDGET
+    }
+}
+
+/// How long to keep the runtime loop going once the last invocation finishes, so that its
+/// response is sent.
+const RESPONSE_FLUSH: Duration = Duration::milliseconds(50);
+
+const IDLE_POLL: Duration = Duration::milliseconds(10);
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+static SHUTDOWN: Notify = Notify::const_new();
+
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether a shutdown signal has arrived, after which no new work should be started.
+pub(crate) fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+fn request() {
+    REQUESTED.store(true, Ordering::SeqCst);
+    SHUTDOWN.notify_waiters();
+}
+
+/// Waits until shutdown is requested, returning immediately if it already has been.
+async fn wait() {
+    let notified = SHUTDOWN.notified();
+    tokio::pin!(notified);
+    notified.as_mut().enable();
+
+    if !requested() {
+        notified.await;
+    }
+}
+
+/// Counts work run via [run_until_shutdown] while it's in flight.
+struct InFlight;
+
+impl InFlight {
+    fn new() -> Self {
+        IN_FLIGHT.fetch_add(1, Ordering::SeqCst);
+        Self
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn in_flight() -> bool {
+    IN_FLIGHT.load(Ordering::SeqCst) > 0
+}
+
+/// Requests shutdown when SIGTERM or SIGINT arrives. Call once per process, before any work
+/// starts: the signal handlers stay installed for the life of the process.
+pub fn listen_for_shutdown() -> Result<()> {
+    let mut sigterm = signal(SignalKind::terminate())?;
+
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = sigterm.recv() => debug!("Received SIGTERM"),
+            result = tokio::signal::ctrl_c() => match result {
+                Ok(()) => debug!("Received SIGINT"),
+                Err(e) => {
+                    warn!("Listen For SIGINT Failed: {:?}", e);
+                    return;
+                }
+            },
+        }
+
+        request();
+    });
+
+    Ok(())
+}
+
+/// Runs `future` to completion. If shutdown is requested first, no further updates are started
+/// and `future` gets up to `budget` more to finish the updates in flight and report on them.
+pub async fn run_until_shutdown<F, T>(future: F, budget: Duration) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    let _in_flight = InFlight::new();
+    tokio::pin!(future);
+
+    tokio::select! {
+        result = &mut future => return result,
+        _ = wait() => {}
+    }
+
+    warn!(
+        "Shutdown Requested, Waiting up to {}ms for In-Flight Updates",
+        budget.num_milliseconds()
+    );
+
+    match tokio::time::timeout(budget.to_std()?, future).await {
+        Ok(result) => result,
+        Err(_) => Err(anyhow!(
+            "In-flight updates didn't finish within the {}ms shutdown budget",
+            budget.num_milliseconds()
+        )),
+    }
+}
+
+/// Runs a Lambda runtime loop until shutdown is requested, then stops taking invocations. The
+/// loop keeps running for up to `budget` while an invocation is in flight, since it's what drives
+/// that invocation and sends its response, and then returns so that the process can exit.
+pub async fn serve_until_shutdown<F, E>(runtime: F, budget: Duration) -> Result<(), E>
+where
+    F: Future<Output = Result<(), E>>,
+{
+    tokio::pin!(runtime);
+
+    tokio::select! {
+        result = &mut runtime => return result,
+        _ = wait() => {}
+    }
+
+    info!("Shutdown Requested, No Longer Accepting Invocations");
+
+    if !in_flight() {
+        return Ok(());
+    }
+
+    let drain = async {
+        while in_flight() {
+            tokio::time::sleep(IDLE_POLL.to_std().unwrap_or_default()).await;
+        }
+        tokio::time::sleep(RESPONSE_FLUSH.to_std().unwrap_or_default()).await;
+    };
+    let limit = (budget + RESPONSE_FLUSH).to_std().unwrap_or_default();
+
+    tokio::select! {
+        result = &mut runtime => result,
+        _ = tokio::time::timeout(limit, drain) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::future;
+
+    // Shutdown is process-wide, so both paths are covered by one test, in order.
+    #[tokio::test(start_paused = true)]
+    async fn test_run_until_shutdown() -> Result<()> {
+        run_until_shutdown(async { Ok(()) }, DEFAULT_BUDGET).await?;
+
+        let res =
+            run_until_shutdown(async { Err::<(), _>(anyhow!("failed")) }, DEFAULT_BUDGET).await;
+        assert!(res.is_err());
+        assert!(!requested());
+
+        let res = run_until_shutdown(
+            async {
+                request();
+                tokio::time::sleep(Duration::seconds(1).to_std()?).await;
+                Ok(7)
+            },
+            DEFAULT_BUDGET,
+        )
+        .await;
+        assert!(requested());
+        assert_eq!(7, res?);
+
+        let res = run_until_shutdown(
+            async {
+                tokio::time::sleep((DEFAULT_BUDGET * 2).to_std()?).await;
+                Ok(())
+            },
+            DEFAULT_BUDGET,
+        )
+        .await;
+        assert!(res.is_err());
+        if let Err(e) = res {
+            assert!(e.to_string().contains("shutdown budget"));
+        }
+
+        // A runtime loop which never finishes on its own still stops.
+        serve_until_shutdown(future::pending::<Result<()>>(), LAMBDA_BUDGET).await?;
+        assert!(!in_flight());
+
+        Ok(())
+    }
+}
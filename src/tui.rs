@@ -0,0 +1,609 @@
+use crate::rollout::{RolloutProgress, Rollouts};
+use crate::{
+    bundle, get_function_names, get_object_md, marker_key, Clients, Config, Record,
+    DEPLOYED_AT_TAG_KEY, FUNCTION_NAME_MD_KEY, STATUS_TAG_KEY, TIER_SEPARATOR,
+};
+use anyhow::Result;
+use aws_sdk_s3::types::Tag;
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, info};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::task::JoinHandle;
+
+/// How often the dashboard refreshes without being asked to.
+const REFRESH_INTERVAL: Duration = Duration::seconds(10);
+
+/// How long to wait for a key press before checking whether a refresh is due.
+const POLL_INTERVAL: Duration = Duration::milliseconds(250);
+
+/// How much of a code SHA-256 to show, which is plenty to tell deployments apart.
+const SHA_LEN: usize = 12;
+
+const BAR_WIDTH: usize = 10;
+
+/// A function mapped from an artifact in the bucket, and how its last deployment went.
+#[derive(Debug, Default)]
+struct FunctionRow {
+    function_name: String,
+    source: String,
+    version: Option<String>,
+    code_sha256: Option<String>,
+    last_modified: Option<String>,
+    /// Lambda's status for the function's most recent code update, e.g. `InProgress`.
+    update_status: Option<String>,
+    deployment_status: Option<String>,
+    deployed_at: Option<String>,
+}
+
+/// Bundles' package functions from their manifests, keyed by bundle key, ETag, and manifest path, so
+/// that each bundle is only downloaded again once it's replaced.
+type Manifests = Mutex<HashMap<(String, String, String), BTreeMap<String, Vec<String>>>>;
+
+/// An object in the bucket which may be an artifact.
+struct Artifact {
+    key: String,
+    e_tag: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Snapshot {
+    functions: Vec<FunctionRow>,
+    rollouts: Vec<RolloutProgress>,
+    errors: Vec<String>,
+    refreshed_at: Option<DateTime<Utc>>,
+}
+
+async fn list_artifacts(s3_client: &aws_sdk_s3::Client, bucket: &str) -> Result<Vec<Artifact>> {
+    let mut artifacts = Vec::new();
+
+    debug!("List Objects: {}", bucket);
+    let mut pages = s3_client
+        .list_objects_v2()
+        .bucket(bucket)
+        .into_paginator()
+        .send();
+    while let Some(page) = pages.next().await {
+        artifacts.extend(page?.contents().iter().filter_map(|o| {
+            o.key().map(|key| Artifact {
+                key: key.to_string(),
+                e_tag: o.e_tag().map(String::from),
+            })
+        }));
+    }
+    info!(
+        "List Objects Succeeded: {} ({} objects)",
+        bucket,
+        artifacts.len()
+    );
+
+    Ok(artifacts)
+}
+
+/// The last deployment's status and time from the source object's tags. Without tags, a
+/// deployment marker still shows that a deployment succeeded, though not when.
+fn last_deployment(tags: &[Tag], has_marker: bool) -> (Option<String>, Option<String>) {
+    let tag = |key: &str| {
+        tags.iter()
+            .find(|t| t.key() == key)
+            .map(|t| t.value().to_string())
+    };
+
+    match tag(STATUS_TAG_KEY) {
+        Some(status) => (Some(status), tag(DEPLOYED_AT_TAG_KEY)),
+        None if has_marker => (Some("succeeded".to_string()), None),
+        None => (None, None),
+    }
+}
+
+async fn get_tags(s3_client: &aws_sdk_s3::Client, record: &Record) -> Result<Vec<Tag>> {
+    let bucket = &record.s3.bucket.name;
+    let key = &record.s3.object.key;
+
+    debug!("Get Object Tagging: {}:{}", bucket, key);
+    let tags = s3_client
+        .get_object_tagging()
+        .bucket(bucket)
+        .key(key)
+        .send()
+        .await?
+        .tag_set;
+
+    Ok(tags)
+}
+
+/// Fills in a function's deployed version and code, from the rollout alias if there is one.
+async fn describe_function(
+    lambda_client: &aws_sdk_lambda::Client,
+    alias: Option<&str>,
+    row: &mut FunctionRow,
+) -> Result<()> {
+    debug!("Get Function Configuration: {}", row.function_name);
+    let output = match lambda_client
+        .get_function_configuration()
+        .function_name(&row.function_name)
+        .set_qualifier(alias.map(String::from))
+        .send()
+        .await
+    {
+        Ok(output) => output,
+        Err(e)
+            if e.as_service_error()
+                .is_some_and(|e| e.is_resource_not_found_exception()) =>
+        {
+            row.update_status = Some("NotFound".to_string());
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    row.version = output.version;
+    row.code_sha256 = output.code_sha256;
+    row.last_modified = output.last_modified;
+    row.update_status = output.last_update_status.map(|s| s.as_str().to_string());
+
+    Ok(())
+}
+
+/// Reads a bundle's package functions from its manifest, downloading it only if it isn't cached.
+async fn package_functions(
+    s3_client: &aws_sdk_s3::Client,
+    manifests: &Manifests,
+    record: &Record,
+    e_tag: Option<&str>,
+    manifest_path: &str,
+) -> Result<BTreeMap<String, Vec<String>>> {
+    let bucket = &record.s3.bucket.name;
+    let key = &record.s3.object.key;
+
+    let cache_key = e_tag.map(|e_tag| (key.clone(), e_tag.to_string(), manifest_path.to_string()));
+    if let Some(cache_key) = &cache_key {
+        if let Some(package_functions) = manifests
+            .lock()
+            .ok()
+            .and_then(|m| m.get(cache_key).cloned())
+        {
+            return Ok(package_functions);
+        }
+    }
+
+    let bundle = bundle::download(s3_client, bucket, key).await?;
+    let package_functions = bundle::package_functions(&bundle, manifest_path)?;
+
+    if let (Some(cache_key), Ok(mut manifests)) = (cache_key, manifests.lock()) {
+        manifests.insert(cache_key, package_functions.clone());
+    }
+
+    Ok(package_functions)
+}
+
+async fn artifact_rows(
+    clients: &Clients,
+    config: &Config,
+    manifests: &Manifests,
+    record: &Record,
+    e_tag: Option<&str>,
+    has_marker: bool,
+) -> Result<Vec<FunctionRow>> {
+    let bucket = &record.s3.bucket.name;
+    let key = &record.s3.object.key;
+    let source = format!("{}:{}", bucket, key);
+
+    let tags = get_tags(&clients.s3, record).await?;
+    let (deployment_status, deployed_at) = last_deployment(&tags, has_marker);
+
+    // Each function and where its code comes from, which for bundles is a package within them.
+    let object_md = get_object_md(&clients.s3, record).await;
+    let functions = match object_md.get(bundle::MANIFEST_MD_KEY) {
+        Some(manifest_path) => {
+            package_functions(&clients.s3, manifests, record, e_tag, manifest_path)
+                .await?
+                .into_iter()
+                .flat_map(|(path, function_names)| {
+                    let source = format!("{}[{}]", source, path);
+                    function_names.into_iter().map(move |f| (f, source.clone()))
+                })
+                .collect::<Vec<_>>()
+        }
+        None => get_function_names(object_md.get(FUNCTION_NAME_MD_KEY), record, &config.groups)?
+            .split([',', TIER_SEPARATOR])
+            .map(|f| (f.to_string(), source.clone()))
+            .collect(),
+    };
+
+    let mut rows = Vec::with_capacity(functions.len());
+    for (function_name, source) in functions {
+        let mut row = FunctionRow {
+            function_name,
+            source,
+            deployment_status: deployment_status.clone(),
+            deployed_at: deployed_at.clone(),
+            ..Default::default()
+        };
+
+        describe_function(&clients.lambda, config.rollout_alias.as_deref(), &mut row).await?;
+
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+/// Gathers the state of every artifact in the bucket and of any in-progress rollouts. Failures are
+/// collected rather than returned, so one bad artifact doesn't hide the rest.
+async fn snapshot(
+    clients: &Clients,
+    config: &Config,
+    manifests: &Manifests,
+    bucket: &str,
+) -> Snapshot {
+    let mut snapshot = Snapshot::default();
+
+    match list_artifacts(&clients.s3, bucket).await {
+        Ok(artifacts) => {
+            let all_keys = artifacts
+                .iter()
+                .map(|a| a.key.as_str())
+                .collect::<HashSet<_>>();
+
+            for artifact in artifacts.iter().filter(|a| a.key.ends_with(".zip")) {
+                let key = &artifact.key;
+                let record = Record {
+                    region: None,
+                    s3: (bucket, key.as_str()).into(),
+                };
+                let has_marker = all_keys.contains(marker_key(key).as_str());
+
+                match artifact_rows(
+                    clients,
+                    config,
+                    manifests,
+                    &record,
+                    artifact.e_tag.as_deref(),
+                    has_marker,
+                )
+                .await
+                {
+                    Ok(rows) => snapshot.functions.extend(rows),
+                    Err(e) => snapshot.errors.push(format!("{}:{}: {}", bucket, key, e)),
+                }
+            }
+        }
+        Err(e) => snapshot.errors.push(format!("{}: {}", bucket, e)),
+    }
+
+    if let Some(rollouts) = Rollouts::new(clients, config) {
+        match rollouts.progress().await {
+            Ok(progress) => snapshot.rollouts = progress,
+            Err(e) => snapshot.errors.push(format!("Rollouts: {}", e)),
+        }
+    }
+
+    snapshot.refreshed_at = Some(Utc::now());
+
+    snapshot
+}
+
+fn short_sha(sha: &str) -> &str {
+    sha.get(..SHA_LEN).unwrap_or(sha)
+}
+
+/// Renders a rollout's weight as a bar, e.g. `███░░░░░░░  30%`.
+fn weight_bar(weight: u8) -> String {
+    let filled = usize::from(weight.min(100)) * BAR_WIDTH / 100;
+    format!(
+        "{}{} {:>3}%",
+        "█".repeat(filled),
+        "░".repeat(BAR_WIDTH - filled),
+        weight
+    )
+}
+
+fn status_style(status: Option<&str>) -> Style {
+    match status {
+        Some("succeeded" | "Successful" | "completed") => Style::new().fg(Color::Green),
+        Some("failed" | "Failed" | "NotFound" | "rolled-back") => Style::new().fg(Color::Red),
+        Some("InProgress" | "in-progress" | "baking") => Style::new().fg(Color::Yellow),
+        _ => Style::new(),
+    }
+}
+
+fn functions_table(functions: &[FunctionRow]) -> Table<'_> {
+    let dash = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+
+    let header = Row::new([
+        "Function",
+        "Source",
+        "Version",
+        "Code SHA-256",
+        "Last Modified",
+        "Update",
+        "Deployment",
+        "Deployed At",
+    ])
+    .style(Style::new().add_modifier(Modifier::BOLD));
+
+    let rows = functions.iter().map(|f| {
+        Row::new([
+            Line::from(f.function_name.as_str()),
+            Line::from(f.source.as_str()),
+            Line::from(dash(&f.version)),
+            Line::from(f.code_sha256.as_deref().map(short_sha).unwrap_or("-")),
+            Line::from(dash(&f.last_modified)),
+            Line::styled(
+                dash(&f.update_status),
+                status_style(f.update_status.as_deref()),
+            ),
+            Line::styled(
+                dash(&f.deployment_status),
+                status_style(f.deployment_status.as_deref()),
+            ),
+            Line::from(dash(&f.deployed_at)),
+        ])
+    });
+
+    Table::new(
+        rows,
+        [
+            Constraint::Fill(2),
+            Constraint::Fill(3),
+            Constraint::Length(8),
+            Constraint::Length(SHA_LEN as u16),
+            Constraint::Length(28),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(20),
+        ],
+    )
+    .header(header)
+}
+
+fn rollouts_table(rollouts: &[RolloutProgress]) -> Table<'_> {
+    let header = Row::new(["Function", "Alias", "Version", "Status", "Traffic"])
+        .style(Style::new().add_modifier(Modifier::BOLD));
+
+    let rows = rollouts.iter().map(|r| {
+        Row::new([
+            Line::from(r.function_name.as_str()),
+            Line::from(r.alias.as_str()),
+            Line::from(r.new_version.as_str()),
+            Line::styled(r.status, status_style(Some(r.status))),
+            Line::from(weight_bar(r.weight)),
+        ])
+    });
+
+    Table::new(
+        rows,
+        [
+            Constraint::Fill(2),
+            Constraint::Fill(1),
+            Constraint::Length(8),
+            Constraint::Length(12),
+            Constraint::Length(BAR_WIDTH as u16 + 5),
+        ],
+    )
+    .header(header)
+}
+
+/// Draws a snapshot, marked as refreshing while the next one is gathered.
+fn draw(frame: &mut Frame, bucket: &str, snapshot: &Snapshot, refreshing: bool) {
+    let errors_height = match snapshot.errors.len() {
+        0 => 0,
+        n => n.min(5) as u16 + 2,
+    };
+
+    let [functions_area, rollouts_area, errors_area, help_area] = Layout::vertical([
+        Constraint::Min(4),
+        Constraint::Length(snapshot.rollouts.len().max(1) as u16 + 3),
+        Constraint::Length(errors_height),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    frame.render_widget(
+        functions_table(&snapshot.functions)
+            .block(Block::bordered().title(format!(" Functions ({}) ", bucket))),
+        functions_area,
+    );
+    frame.render_widget(
+        rollouts_table(&snapshot.rollouts).block(Block::bordered().title(" Rollouts ")),
+        rollouts_area,
+    );
+    frame.render_widget(
+        Paragraph::new(
+            snapshot
+                .errors
+                .iter()
+                .map(|e| Line::from(e.as_str()))
+                .collect::<Vec<_>>(),
+        )
+        .style(Style::new().fg(Color::Red))
+        .block(Block::bordered().title(" Errors ")),
+        errors_area,
+    );
+
+    let refreshed_at = match snapshot.refreshed_at {
+        Some(refreshed_at) => refreshed_at.format("%H:%M:%S UTC").to_string(),
+        None => "never".to_string(),
+    };
+    frame.render_widget(
+        Line::from(format!(
+            " Refreshed {} | r: refresh | q: quit",
+            refreshed_at
+        ))
+        .style(Style::new().add_modifier(Modifier::DIM)),
+        help_area,
+    );
+
+    if refreshing {
+        frame.render_widget(
+            Line::from(" Refreshing...").style(Style::new().fg(Color::Yellow)),
+            help_area,
+        );
+    }
+}
+
+/// Gathers a snapshot in the background, so that keys are still handled while it's gathered.
+fn spawn_snapshot(
+    clients: &Arc<Clients>,
+    config: &Arc<Config>,
+    manifests: &Arc<Manifests>,
+    bucket: &str,
+) -> JoinHandle<Snapshot> {
+    let clients = clients.clone();
+    let config = config.clone();
+    let manifests = manifests.clone();
+    let bucket = bucket.to_string();
+
+    tokio::spawn(async move { snapshot(&clients, &config, &manifests, &bucket).await })
+}
+
+async fn run(
+    terminal: &mut DefaultTerminal,
+    clients: Arc<Clients>,
+    config: Arc<Config>,
+    bucket: &str,
+) -> Result<()> {
+    let mut snapshot = Snapshot::default();
+    let mut refreshing: Option<JoinHandle<Snapshot>> = None;
+    let mut refreshed_at: Option<Instant> = None;
+    let mut refresh_requested = false;
+    let manifests = Arc::new(Manifests::default());
+    let refresh_interval = REFRESH_INTERVAL.to_std()?;
+    let poll_interval = POLL_INTERVAL.to_std()?;
+
+    loop {
+        if let Some(handle) = refreshing.take_if(|h| h.is_finished()) {
+            snapshot = handle.await?;
+            refreshed_at = Some(Instant::now());
+        }
+
+        if refreshing.is_none()
+            && (refresh_requested || refreshed_at.is_none_or(|r| r.elapsed() >= refresh_interval))
+        {
+            refreshing = Some(spawn_snapshot(&clients, &config, &manifests, bucket));
+            refresh_requested = false;
+        }
+
+        terminal.draw(|frame| draw(frame, bucket, &snapshot, refreshing.is_some()))?;
+
+        if event::poll(poll_interval)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    match key.code {
+                        KeyCode::Char('q') | KeyCode::Esc => break,
+                        KeyCode::Char('r') => refresh_requested = true,
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(handle) = refreshing {
+        handle.abort();
+    }
+
+    Ok(())
+}
+
+/// Runs an interactive dashboard of the functions mapped from a bucket's artifacts: their deployed
+/// versions and code, how their last deployments went, and the progress of running updates and
+/// rollouts. Refreshes periodically, or on `r`, until `q` is pressed.
+pub async fn tui(bucket: &str, region: Option<String>, config: &Config) -> Result<()> {
+    let clients = Arc::new(Clients::new(region).await);
+    let config = Arc::new(config.clone());
+
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, clients, config, bucket).await;
+    ratatui::restore();
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn tag(key: &str, value: &str) -> Tag {
+        Tag::builder()
+            .key(key)
+            .value(value)
+            .build()
+            .expect("tag is valid")
+    }
+
+    #[test]
+    fn test_last_deployment() {
+        let tags = vec![
+            tag("team", "payments"),
+            tag(STATUS_TAG_KEY, "failed"),
+            tag(DEPLOYED_AT_TAG_KEY, "2024-05-01T12:00:00Z"),
+        ];
+        assert_eq!(
+            (
+                Some("failed".to_string()),
+                Some("2024-05-01T12:00:00Z".to_string())
+            ),
+            last_deployment(&tags, true)
+        );
+
+        assert_eq!(
+            (Some("succeeded".to_string()), None),
+            last_deployment(&[tag("team", "payments")], true)
+        );
+        assert_eq!((None, None), last_deployment(&[], false));
+    }
+
+    #[test]
+    fn test_weight_bar() {
+        assert_eq!("░░░░░░░░░░   0%", weight_bar(0));
+        assert_eq!("███░░░░░░░  30%", weight_bar(30));
+        assert_eq!("██████████ 100%", weight_bar(100));
+    }
+
+    #[test]
+    fn test_draw() -> Result<()> {
+        let snapshot = Snapshot {
+            functions: vec![FunctionRow {
+                function_name: "foo".to_string(),
+                source: "bucket:foo.zip".to_string(),
+                version: Some("7".to_string()),
+                code_sha256: Some("abcdef0123456789abcdef".to_string()),
+                update_status: Some("InProgress".to_string()),
+                deployment_status: Some("succeeded".to_string()),
+                ..Default::default()
+            }],
+            errors: vec!["bucket:bar.zip: AccessDenied".to_string()],
+            ..Default::default()
+        };
+
+        let mut terminal = Terminal::new(TestBackend::new(160, 16))?;
+        terminal.draw(|frame| draw(frame, "bucket", &snapshot, true))?;
+
+        let screen = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|c| c.symbol())
+            .collect::<String>();
+
+        assert!(screen.contains("Functions (bucket)"));
+        assert!(screen.contains("abcdef012345 "));
+        assert!(screen.contains("InProgress"));
+        assert!(screen.contains("bucket:bar.zip: AccessDenied"));
+        assert!(screen.contains("Refreshing..."));
+
+        Ok(())
+    }
+}